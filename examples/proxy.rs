@@ -0,0 +1,39 @@
+use std::env;
+use std::error::Error;
+use std::net::SocketAddr;
+use walletconnect::transport::{Proxy, WalletConnect};
+use walletconnect::{qr, Client, Metadata};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    env_logger::init();
+
+    let client = Client::new(
+        "examples-proxy",
+        Metadata {
+            description: "WalletConnect-rs JSON RPC proxy example.".into(),
+            url: "https://github.com/nlordell/walletconnect-rs".parse()?,
+            icons: vec!["https://avatars0.githubusercontent.com/u/4210206".parse()?],
+            name: "WalletConnect-rs Proxy Example".into(),
+        },
+    )?;
+
+    let (accounts, chain_id) = client.ensure_session(qr::print_with_url).await?;
+    println!("Connected to chain {} with accounts:", chain_id);
+    for account in &accounts {
+        println!(" - {:?}", account);
+    }
+
+    let wc = match env::var("INFURA_PROJECT_ID") {
+        Ok(project_id) => WalletConnect::infura(client, &project_id)?,
+        Err(_) => WalletConnect::new(client)?,
+    };
+
+    let addr: SocketAddr = env::var("PROXY_ADDR")
+        .unwrap_or_else(|_| "127.0.0.1:8545".to_owned())
+        .parse()?;
+    println!("Serving JSON RPC on http://{}", addr);
+    Proxy::new(wc).serve(&addr).await?;
+
+    Ok(())
+}
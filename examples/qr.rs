@@ -1,16 +1,17 @@
 use std::error::Error;
 use std::process;
-use walletconnect::{qr, Client, Metadata, Transaction};
+use walletconnect::blocking::Client;
+use walletconnect::{qr, Metadata, Transaction};
 
 fn main() {
     env_logger::init();
-    if let Err(err) = futures::executor::block_on(run()) {
+    if let Err(err) = run() {
         log::error!("{}", err);
         process::exit(1);
     }
 }
 
-async fn run() -> Result<(), Box<dyn Error>> {
+fn run() -> Result<(), Box<dyn Error>> {
     let client = Client::new(
         "examples-qr",
         Metadata {
@@ -21,21 +22,19 @@ async fn run() -> Result<(), Box<dyn Error>> {
         },
     )?;
 
-    let (accounts, _) = client.ensure_session(qr::print_with_url).await?;
+    let (accounts, _) = client.ensure_session(qr::print_with_url)?;
 
     println!("Connected accounts:");
     for account in &accounts {
         println!(" - {:?}", account);
     }
 
-    let tx = client
-        .send_transaction(Transaction {
-            from: accounts[0],
-            to: Some("000102030405060708090a0b0c0d0e0f10111213".parse()?),
-            value: 1_000_000_000_000_000u128.into(),
-            ..Transaction::default()
-        })
-        .await?;
+    let tx = client.send_transaction(Transaction {
+        from: accounts[0],
+        to: Some("000102030405060708090a0b0c0d0e0f10111213".parse()?),
+        value: 1_000_000_000_000_000u128.into(),
+        ..Transaction::default()
+    })?;
 
     println!("Transaction sent:\n  https://etherscan.io/tx/{:?}", tx);
 
@@ -21,7 +21,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     client.ensure_session(qr::print_with_url).await?;
 
-    let wc = WalletConnect::new(client, env::var("INFURA_PROJECT_ID")?)?;
+    let wc = match env::var("INFURA_PROJECT_ID") {
+        Ok(project_id) => WalletConnect::infura(client, &project_id)?,
+        Err(_) => WalletConnect::new(client)?,
+    };
     let web3 = Web3::new(wc);
 
     let accounts = web3.eth().accounts().await?;
@@ -0,0 +1,320 @@
+//! Integration with `alloy` transports and signers.
+
+use crate::client::{is_wallet_method, CallError, Client, NotConnectedError};
+use crate::hex;
+use alloy_dyn_abi::eip712::TypedData;
+use alloy_json_rpc::{
+    ErrorPayload, RequestPacket, Response, ResponsePacket, ResponsePayload, SerializedRequest,
+};
+use alloy_primitives::{Address, Bytes, ChainId, Signature, B256};
+use alloy_signer::{Signer, UnsupportedSignerOperation};
+use alloy_transport::{TransportError, TransportErrorKind, TransportFut};
+use alloy_transport_http::{reqwest, Http};
+use async_trait::async_trait;
+use serde_json::value;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use url::Url;
+
+/// A layer that routes account and signing requests to the wallet connected
+/// to a WalletConnect session.
+#[derive(Clone, Debug)]
+pub struct WalletLayer {
+    client: Arc<Client>,
+}
+
+impl WalletLayer {
+    pub fn new(client: impl Into<Arc<Client>>) -> Self {
+        WalletLayer {
+            client: client.into(),
+        }
+    }
+}
+
+impl<S> Layer<S> for WalletLayer {
+    type Service = WalletTransport<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        WalletTransport {
+            client: self.client.clone(),
+            inner,
+        }
+    }
+}
+
+/// An `alloy` transport that handles account and signing requests with the
+/// wallet connected to a WalletConnect session, and forwards all other
+/// requests to a node transport.
+///
+/// Node requests are always sent to the configured transport, so it should
+/// be for the session's chain.
+#[derive(Clone, Debug)]
+pub struct WalletTransport<T> {
+    client: Arc<Client>,
+    inner: T,
+}
+
+impl WalletTransport<Http<reqwest::Client>> {
+    /// Creates a new WalletConnect transport that forwards node requests to
+    /// an HTTP RPC endpoint.
+    pub fn http(client: impl Into<Arc<Client>>, url: Url) -> Self {
+        WalletTransport::new(client, Http::new(url))
+    }
+}
+
+impl<T> WalletTransport<T> {
+    pub fn new(client: impl Into<Arc<Client>>, inner: T) -> Self {
+        WalletLayer::new(client).layer(inner)
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Returns the underlying node transport.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> Service<RequestPacket> for WalletTransport<T>
+where
+    T: Service<
+            RequestPacket,
+            Response = ResponsePacket,
+            Error = TransportError,
+            Future = TransportFut<'static>,
+        > + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, packet: RequestPacket) -> Self::Future {
+        let client = self.client.clone();
+        let mut inner = self.inner.clone();
+        Box::pin(async move {
+            match packet {
+                RequestPacket::Single(request) if is_wallet_method(request.method()) => {
+                    let response = wallet_response(&client, request).await?;
+                    Ok(ResponsePacket::Single(response))
+                }
+                RequestPacket::Batch(requests)
+                    if requests
+                        .iter()
+                        .any(|request| is_wallet_method(request.method())) =>
+                {
+                    // NOTE: Batch responses are matched to requests by ID, so
+                    // node requests are sent as a single batch and the wallet
+                    // responses are appended to its responses.
+                    let (wallet, node): (Vec<_>, Vec<_>) = requests
+                        .into_iter()
+                        .partition(|request| is_wallet_method(request.method()));
+
+                    let mut responses = if node.is_empty() {
+                        Vec::new()
+                    } else {
+                        match inner.call(RequestPacket::Batch(node)).await? {
+                            ResponsePacket::Single(response) => vec![response],
+                            ResponsePacket::Batch(responses) => responses,
+                        }
+                    };
+                    for request in wallet {
+                        responses.push(wallet_response(&client, request).await?);
+                    }
+                    Ok(ResponsePacket::Batch(responses))
+                }
+                packet => inner.call(packet).await,
+            }
+        })
+    }
+}
+
+/// Handles an account or signing request with the wallet.
+async fn wallet_response(
+    client: &Client,
+    request: SerializedRequest,
+) -> Result<Response, TransportError> {
+    let id = request.id().clone();
+    let params = match request.params() {
+        Some(params) => serde_json::from_str::<Vec<Value>>(params.get()),
+        None => Ok(Vec::new()),
+    };
+    let result = match params {
+        Ok(params) => client.wallet_request(request.method(), params).await,
+        Err(err) => Err(jsonrpc_core::Error::invalid_params(err.to_string()).into()),
+    };
+
+    Ok(Response {
+        id,
+        payload: response_payload(result)?,
+    })
+}
+
+/// Converts the result of a wallet request into a JSON RPC response payload,
+/// preserving wallet JSON RPC errors so that their error codes can be
+/// classified.
+fn response_payload(result: Result<Value, CallError>) -> Result<ResponsePayload, TransportError> {
+    let payload = match result {
+        Ok(result) => {
+            ResponsePayload::Success(value::to_raw_value(&result).map_err(TransportError::ser_err)?)
+        }
+        Err(CallError::Rpc(err)) => ResponsePayload::Failure(ErrorPayload {
+            code: err.code.code(),
+            message: err.message.into(),
+            data: err
+                .data
+                .as_ref()
+                .map(value::to_raw_value)
+                .transpose()
+                .map_err(TransportError::ser_err)?,
+        }),
+        Err(err) => return Err(TransportErrorKind::custom(err)),
+    };
+    Ok(payload)
+}
+
+/// An `alloy` signer that requests signatures from the wallet connected to a
+/// WalletConnect session.
+///
+/// Wallets only sign messages and typed data, and not raw hashes.
+#[derive(Clone, Debug)]
+pub struct WalletSigner {
+    client: Arc<Client>,
+    address: ethers_core::types::Address,
+    chain_id: Option<ChainId>,
+}
+
+impl WalletSigner {
+    /// Creates a new signer for the session's first account and current
+    /// chain.
+    pub fn new(client: impl Into<Arc<Client>>) -> Result<Self, NotConnectedError> {
+        let client = client.into();
+        let (accounts, chain_id) = client.accounts()?;
+        let address = *accounts.first().ok_or(NotConnectedError)?;
+        Ok(WalletSigner {
+            client,
+            address,
+            chain_id: Some(chain_id),
+        })
+    }
+
+    /// Sets the session account to sign with.
+    pub fn with_address(mut self, address: Address) -> Self {
+        self.address = address.into_array().into();
+        self
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+}
+
+#[async_trait]
+impl Signer for WalletSigner {
+    async fn sign_hash(&self, _: &B256) -> alloy_signer::Result<Signature> {
+        Err(alloy_signer::Error::UnsupportedOperation(
+            UnsupportedSignerOperation::SignHash,
+        ))
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> alloy_signer::Result<Signature> {
+        let data = format!("0x{}", hex::encode(message));
+        let address = format!("{:?}", self.address);
+        // NOTE: Request the raw signature bytes, so that signatures that are
+        // not 65 bytes long, such as ones from smart-contract wallets, are
+        // reported as signature errors.
+        let signature = self
+            .client
+            .send_custom_request("personal_sign", vec![json!(data), json!(address)])
+            .await
+            .map_err(alloy_signer::Error::other)?;
+        let signature =
+            serde_json::from_value::<Bytes>(signature).map_err(alloy_signer::Error::other)?;
+        Ok(Signature::try_from(signature.as_ref())?)
+    }
+
+    async fn sign_dynamic_typed_data(
+        &self,
+        payload: &TypedData,
+    ) -> alloy_signer::Result<Signature> {
+        let typed_data = serde_json::to_value(payload).map_err(alloy_signer::Error::other)?;
+        let signature = self
+            .client
+            .sign_typed_data(self.address, &typed_data)
+            .await
+            .map_err(alloy_signer::Error::other)?;
+        Ok(Signature::try_from(signature.as_ref())?)
+    }
+
+    fn address(&self) -> Address {
+        Address::from(self.address.0)
+    }
+
+    fn chain_id(&self) -> Option<ChainId> {
+        self.chain_id
+    }
+
+    fn set_chain_id(&mut self, chain_id: Option<ChainId>) {
+        self.chain_id = chain_id;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{connect, Bridge};
+    use futures::executor::block_on;
+    use jsonrpc_core::ErrorCode;
+
+    #[test]
+    fn wallet_response_payloads() {
+        let payload = response_payload(Ok(json!("0x1"))).unwrap();
+        assert_eq!(payload.as_success().unwrap().get(), r#""0x1""#);
+
+        let payload = response_payload(Err(CallError::Rpc(jsonrpc_core::Error {
+            code: ErrorCode::ServerError(4001),
+            message: "User rejected the request.".into(),
+            data: Some(json!({ "reason": "rejected" })),
+        })))
+        .unwrap();
+        let err = payload.as_error().unwrap();
+        assert_eq!(err.code, 4001);
+        assert_eq!(err.message, "User rejected the request.");
+        assert_eq!(err.data.as_ref().unwrap().get(), r#"{"reason":"rejected"}"#);
+
+        assert!(matches!(
+            response_payload(Err(CallError::NotConnected)),
+            Err(TransportError::Transport(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_message_signatures() {
+        let bridge = Bridge::new();
+        let (client, session) = connect(&bridge, true);
+        let signer = WalletSigner::new(client).unwrap();
+
+        let signature = format!("0x{}1b", "11".repeat(64));
+        bridge.respond(&session, json!(signature));
+        assert_eq!(
+            block_on(signer.sign_message(b"hello")).unwrap().as_bytes()[..],
+            hex::decode(&signature[2..]).unwrap()[..],
+        );
+
+        bridge.respond(&session, json!("0x01"));
+        assert!(matches!(
+            block_on(signer.sign_message(b"hello")),
+            Err(alloy_signer::Error::SignatureError(_))
+        ));
+    }
+}
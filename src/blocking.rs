@@ -0,0 +1,204 @@
+//! A blocking WalletConnect client for synchronous code.
+
+use crate::client::{
+    self, CallError, ConnectorError, NotConnectedError, SessionError, SocketError,
+};
+use crate::protocol::{Metadata, Transaction};
+use crate::siwe::{self, SignInError};
+use crate::uri::Uri;
+use crate::verify::PersonalSignatureError;
+use ethers_core::types::{Address, Bytes, Signature, H256};
+use futures::executor;
+use futures::future::{self, Either};
+use futures_timer::Delay;
+use serde_json::Value;
+use std::future::Future;
+use std::path::PathBuf;
+use std::time::Duration;
+use thiserror::Error;
+
+/// A WalletConnect client that blocks the current thread on each request.
+///
+/// The client drives its requests to completion on the calling thread, so it
+/// must not be used from within an asynchronous runtime.
+#[derive(Debug)]
+pub struct Client {
+    inner: client::Client,
+    timeout: Option<Duration>,
+}
+
+impl Client {
+    pub fn new(
+        profile: impl Into<PathBuf>,
+        meta: impl Into<Metadata>,
+    ) -> Result<Self, ConnectorError> {
+        Ok(client::Client::new(profile, meta)?.into())
+    }
+
+    pub fn with_options(options: client::Options) -> Result<Self, ConnectorError> {
+        Ok(client::Client::with_options(options)?.into())
+    }
+
+    /// Sets the maximum duration to wait for each request, including waiting
+    /// for the user to approve a new session.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Returns the underlying asynchronous client.
+    pub fn get_ref(&self) -> &client::Client {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> client::Client {
+        self.inner
+    }
+
+    pub fn accounts(&self) -> Result<(Vec<Address>, u64), NotConnectedError> {
+        self.inner.accounts()
+    }
+
+    pub fn ensure_session<F>(&self, f: F) -> Result<(Vec<Address>, u64), Error<SessionError>>
+    where
+        F: FnOnce(Uri),
+    {
+        self.block_on(self.inner.ensure_session(f))
+    }
+
+    pub fn probe(&self, timeout: Duration) -> Result<bool, Error<CallError>> {
+        self.block_on(self.inner.probe(timeout))
+    }
+
+    pub fn send_transaction(&self, transaction: Transaction) -> Result<H256, Error<CallError>> {
+        self.block_on(self.inner.send_transaction(transaction))
+    }
+
+    pub fn sign_transaction(&self, transaction: Transaction) -> Result<Bytes, Error<CallError>> {
+        self.block_on(self.inner.sign_transaction(transaction))
+    }
+
+    pub fn personal_sign(&self, data: &[&str]) -> Result<Signature, Error<CallError>> {
+        self.block_on(self.inner.personal_sign(data))
+    }
+
+    pub fn sign_message(&self, address: Address, data: &str) -> Result<Bytes, Error<CallError>> {
+        self.block_on(self.inner.sign_message(address, data))
+    }
+
+    pub fn sign_typed_data(
+        &self,
+        address: Address,
+        typed_data: &Value,
+    ) -> Result<Bytes, Error<CallError>> {
+        self.block_on(self.inner.sign_typed_data(address, typed_data))
+    }
+
+    pub fn send_custom_request(
+        &self,
+        method: &str,
+        params: Vec<Value>,
+    ) -> Result<Value, Error<CallError>> {
+        self.block_on(self.inner.send_custom_request(method, params))
+    }
+
+    pub fn sign_in_message(&self) -> Result<siwe::Message, NotConnectedError> {
+        self.inner.sign_in_message()
+    }
+
+    pub fn sign_in(&self, message: &siwe::Message) -> Result<Signature, Error<SignInError>> {
+        self.block_on(self.inner.sign_in(message))
+    }
+
+    pub fn verify_personal_sign(
+        &self,
+        message: &str,
+        signature: &Signature,
+    ) -> Result<Address, PersonalSignatureError> {
+        self.inner.verify_personal_sign(message, signature)
+    }
+
+    pub fn close(self) -> Result<(), SocketError> {
+        self.inner.close()
+    }
+
+    fn block_on<F, T, E>(&self, request: F) -> Result<T, Error<E>>
+    where
+        F: Future<Output = Result<T, E>>,
+    {
+        let request = Box::pin(request);
+        let result = match self.timeout {
+            Some(timeout) => {
+                let timeout = Delay::new(timeout);
+                match executor::block_on(future::select(request, timeout)) {
+                    Either::Left((result, _)) => result,
+                    Either::Right(_) => return Err(Error::Timeout),
+                }
+            }
+            None => executor::block_on(request),
+        };
+
+        result.map_err(Error::Request)
+    }
+}
+
+impl From<client::Client> for Client {
+    fn from(inner: client::Client) -> Self {
+        Client {
+            inner,
+            timeout: None,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error<E> {
+    #[error("request timed out")]
+    Timeout,
+    #[error(transparent)]
+    Request(E),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{connect, Bridge};
+
+    fn client(connected: bool) -> Client {
+        let (client, _) = connect(&Bridge::new(), connected);
+        Client::from(client).with_timeout(Duration::from_millis(100))
+    }
+
+    #[test]
+    fn times_out_requests() {
+        let client = client(true);
+        assert!(matches!(
+            client.send_custom_request("eth_chainId", vec![]),
+            Err(Error::Timeout),
+        ));
+        assert!(!client.probe(Duration::from_millis(10)).unwrap());
+    }
+
+    #[test]
+    fn resets_pending_session_on_timeout() {
+        let client = client(false);
+        let mut uris = 0;
+        for _ in 0..2 {
+            // NOTE: The second attempt would fail with a pending session
+            // error if the timed out request was still considered pending.
+            assert!(matches!(
+                client.ensure_session(|_| uris += 1),
+                Err(Error::Timeout),
+            ));
+        }
+        assert_eq!(uris, 2);
+    }
+}
@@ -1,17 +1,31 @@
 mod core;
+mod manager;
 mod options;
+mod profiles;
 mod session;
 mod socket;
 mod storage;
 
 use self::core::Connector;
 pub use self::core::{CallError, ConnectorError, NotConnectedError, SessionError};
+pub use self::manager::SessionManager;
 pub use self::options::{Connection, Options, DEFAULT_BRIDGE_URL};
+pub use self::profiles::{ProfileInfo, Profiles};
+pub use self::session::{SchemaError, Session, SessionState};
 pub use self::socket::SocketError;
+pub use self::storage::{
+    FileStore, MemoryStore, ProfileKey, ProfileLock, SessionStore, StorageError,
+};
+use crate::hex;
 use crate::protocol::{Metadata, Transaction};
+use crate::siwe::{self, SignInError};
 use crate::uri::Uri;
-use ethers_core::types::{Address, Bytes, Signature, H256};
+use crate::verify::{self, PersonalSignatureError};
+use ethers_core::types::{Address, Bytes, Signature, H256, U64};
+use futures::channel::mpsc::UnboundedReceiver;
+use serde_json::{json, Value};
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct Client {
@@ -36,6 +50,11 @@ impl Client {
         self.connection.accounts()
     }
 
+    /// Subscribes to session state changes, starting with the current state.
+    pub fn session_updates(&self) -> UnboundedReceiver<SessionState> {
+        self.connection.subscribe()
+    }
+
     pub async fn ensure_session<F>(&self, f: F) -> Result<(Vec<Address>, u64), SessionError>
     where
         F: FnOnce(Uri),
@@ -43,6 +62,10 @@ impl Client {
         self.connection.ensure_session(f).await
     }
 
+    pub async fn probe(&self, timeout: Duration) -> Result<bool, CallError> {
+        self.connection.probe(timeout).await
+    }
+
     pub async fn send_transaction(&self, transaction: Transaction) -> Result<H256, CallError> {
         self.connection.send_transaction(transaction).await
     }
@@ -51,12 +74,377 @@ impl Client {
         self.connection.sign_transaction(transaction).await
     }
 
+    /// Requests a `personal_sign` signature from the wallet.
+    ///
+    /// Note that this fails with [`CallError::Signature`] for signatures that
+    /// are not 65-byte ECDSA signatures, such as ones from smart-contract
+    /// wallets.
     pub async fn personal_sign(&self, data: &[&str]) -> Result<Signature, CallError> {
-        let sig = self.connection.personal_sign(data).await?;
-        Ok(sig.as_ref().try_into().unwrap())
+        let signature = self.connection.personal_sign(data).await?;
+        Ok(Signature::try_from(signature.as_ref())?)
+    }
+
+    pub async fn sign_message(&self, address: Address, data: &str) -> Result<Bytes, CallError> {
+        self.connection.sign_message(address, data).await
+    }
+
+    pub async fn sign_typed_data(
+        &self,
+        address: Address,
+        typed_data: &Value,
+    ) -> Result<Bytes, CallError> {
+        self.connection.sign_typed_data(address, typed_data).await
+    }
+
+    /// Sends an arbitrary JSON RPC request to the connected wallet.
+    pub async fn send_custom_request(
+        &self,
+        method: &str,
+        params: Vec<Value>,
+    ) -> Result<Value, CallError> {
+        self.connection.send_custom_request(method, params).await
+    }
+
+    /// Handles an account or signing request for a dapp provider.
+    ///
+    /// Account and chain ID requests are answered from the session state,
+    /// while all other requests are sent to the wallet.
+    pub async fn wallet_request(
+        &self,
+        method: &str,
+        mut params: Vec<Value>,
+    ) -> Result<Value, CallError> {
+        let method = wallet_method(method);
+        match method {
+            "eth_accounts" | "eth_requestAccounts" => {
+                let (accounts, _) = self.accounts()?;
+                Ok(json!(accounts))
+            }
+            "eth_chainId" => {
+                let (_, chain_id) = self.accounts()?;
+                Ok(json!(U64::from(chain_id)))
+            }
+            "eth_sendTransaction" | "eth_signTransaction" => {
+                let transaction = params.into_iter().next().ok_or_else(|| {
+                    jsonrpc_core::Error::invalid_params(format!(
+                        "missing transaction for '{}'",
+                        method
+                    ))
+                })?;
+                log::trace!(">>{}", transaction);
+                let transaction = serde_json::from_value(transaction)?;
+                if method == "eth_sendTransaction" {
+                    Ok(json!(self.send_transaction(transaction).await?))
+                } else {
+                    Ok(json!(self.sign_transaction(transaction).await?))
+                }
+            }
+            _ => {
+                // NOTE: Some libraries include the account password as a third
+                // parameter to `personal_sign`, which wallets don't expect.
+                if method == "personal_sign" {
+                    params.truncate(2);
+                }
+                self.send_custom_request(method, params).await
+            }
+        }
+    }
+
+    /// Creates a Sign-In With Ethereum message for the session's first account
+    /// and the dapp's metadata URL.
+    pub fn sign_in_message(&self) -> Result<siwe::Message, NotConnectedError> {
+        let (accounts, chain_id) = self.accounts()?;
+        let address = *accounts.first().ok_or(NotConnectedError)?;
+        Ok(siwe::Message::for_metadata(
+            &self.connection.metadata(),
+            address,
+            chain_id,
+        ))
+    }
+
+    /// Requests a signature for a Sign-In With Ethereum message and verifies
+    /// the signer, domain, nonce and validity period of the result.
+    pub async fn sign_in(&self, message: &siwe::Message) -> Result<Signature, SignInError> {
+        let text = message.to_string();
+        let data = format!("0x{}", hex::encode(&text));
+        let address = format!("{:?}", message.address);
+        let signature = self.connection.personal_sign(&[&data, &address]).await?;
+        let signature = Signature::try_from(signature.as_ref())?;
+
+        siwe::verify(
+            &text,
+            &signature,
+            &siwe::VerifyOptions::for_message(message),
+        )?;
+        Ok(signature)
+    }
+
+    /// Verifies a `personal_sign` signature for a message, returning the
+    /// session account that signed it.
+    pub fn verify_personal_sign(
+        &self,
+        message: &str,
+        signature: &Signature,
+    ) -> Result<Address, PersonalSignatureError> {
+        let (accounts, _) = self.accounts()?;
+        verify::verify_personal_signature(message, signature, &accounts)
     }
 
     pub fn close(self) -> Result<(), SocketError> {
         self.connection.close()
     }
 }
+
+/// Returns true for account and signing methods that are handled by the
+/// wallet instead of a node.
+pub fn is_wallet_method(method: &str) -> bool {
+    matches!(
+        method,
+        "eth_accounts"
+            | "eth_requestAccounts"
+            | "eth_chainId"
+            | "eth_sendTransaction"
+            | "eth_signTransaction"
+            | "eth_sign"
+            | "personal_sign"
+            | "eth_signTypedData"
+            | "eth_signTypedData_v1"
+            | "eth_signTypedData_v3"
+            | "eth_signTypedData_v4"
+            | "personal_listAccounts"
+            | "personal_sendTransaction"
+            | "personal_signTransaction"
+    )
+}
+
+/// Returns the `eth_*` equivalent of legacy `personal_*` account and
+/// transaction methods.
+///
+/// The `personal_*` variants take an additional account password parameter,
+/// which is ignored since the wallet manages its own accounts.
+pub fn wallet_method(method: &str) -> &str {
+    match method {
+        "personal_listAccounts" => "eth_accounts",
+        "personal_sendTransaction" => "eth_sendTransaction",
+        "personal_signTransaction" => "eth_signTransaction",
+        method => method,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::crypto::Key;
+    use crate::protocol::{SocketMessage, SocketMessageKind, Topic};
+    use futures::executor;
+    use futures::StreamExt;
+    use jsonrpc_core::{MethodCall, Output, Version};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Instant;
+    use url::Url;
+
+    /// Returns options for the test profile with the specified session store.
+    pub fn options(store: Arc<dyn SessionStore>) -> Options {
+        Options {
+            store,
+            ..Options::new(
+                "test",
+                Metadata {
+                    description: "walletconnect-rs tests".into(),
+                    url: "https://github.com/nlordell/walletconnect-rs"
+                        .parse()
+                        .unwrap(),
+                    icons: vec![],
+                    name: "walletconnect-rs".into(),
+                },
+            )
+        }
+    }
+
+    type Responders = HashMap<Topic, (Topic, Key, Value)>;
+
+    /// A stand-in bridge that lets tests publish messages to the topics that
+    /// clients subscribed to, and optionally responds to requests for
+    /// sessions.
+    pub struct Bridge {
+        pub url: Url,
+        subscribers: Arc<Mutex<HashMap<Topic, parity_ws::Sender>>>,
+        responders: Arc<Mutex<Responders>>,
+    }
+
+    impl Bridge {
+        pub fn new() -> Self {
+            let subscribers = Arc::new(Mutex::new(HashMap::new()));
+            let responders = Arc::new(Mutex::new(Responders::new()));
+            let server = parity_ws::WebSocket::new({
+                let (subscribers, responders) = (subscribers.clone(), responders.clone());
+                move |out: parity_ws::Sender| {
+                    let (subscribers, responders) = (subscribers.clone(), responders.clone());
+                    move |message: parity_ws::Message| {
+                        let message = serde_json::from_str::<SocketMessage>(message.as_text()?)
+                            .expect("invalid socket message");
+                        match message.kind {
+                            SocketMessageKind::Sub => {
+                                subscribers
+                                    .lock()
+                                    .unwrap()
+                                    .insert(message.topic, out.clone());
+                            }
+                            SocketMessageKind::Pub => {
+                                if let Some((client_id, key, result)) =
+                                    responders.lock().unwrap().get(&message.topic)
+                                {
+                                    let request = key.open(&message.payload.unwrap()).unwrap();
+                                    let request =
+                                        serde_json::from_slice::<MethodCall>(&request).unwrap();
+                                    let response = Output::from(
+                                        Ok(result.clone()),
+                                        request.id,
+                                        Some(Version::V2),
+                                    );
+                                    out.send(sealed(client_id, key, &json!(response)))?;
+                                }
+                            }
+                        }
+                        Ok(())
+                    }
+                }
+            })
+            .unwrap()
+            .bind("127.0.0.1:0")
+            .unwrap();
+            let url = format!("http://{}", server.local_addr().unwrap())
+                .parse()
+                .unwrap();
+            thread::spawn(move || server.run());
+
+            Bridge {
+                url,
+                subscribers,
+                responders,
+            }
+        }
+
+        /// Responds to all requests from the session's client with the
+        /// specified result.
+        pub fn respond(&self, session: &Session, result: Value) {
+            let mut responders = self.responders.lock().unwrap();
+            for topic in session.peer_id.iter().chain([&session.handshake_topic]) {
+                responders.insert(
+                    topic.clone(),
+                    (
+                        session.client_id.clone(),
+                        session.key.clone(),
+                        result.clone(),
+                    ),
+                );
+            }
+        }
+
+        /// Publishes a payload to a topic, waiting for a client to subscribe
+        /// to it first.
+        fn publish(&self, topic: &Topic, key: &Key, payload: Value) {
+            let start = Instant::now();
+            let out = loop {
+                if let Some(out) = self.subscribers.lock().unwrap().get(topic) {
+                    break out.clone();
+                }
+                assert!(start.elapsed() < Duration::from_secs(10), "timed out");
+                thread::sleep(Duration::from_millis(10));
+            };
+            out.send(sealed(topic, key, &payload)).unwrap();
+        }
+
+        /// Sends a session update from the wallet to the session's client.
+        pub fn update(&self, session: &Session, approved: bool, chain_id: u64) {
+            self.publish(
+                &session.client_id,
+                &session.key,
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "method": "wc_sessionUpdate",
+                    "params": [{
+                        "approved": approved,
+                        "accounts": session.accounts,
+                        "chainId": chain_id,
+                    }],
+                }),
+            );
+        }
+    }
+
+    /// Returns a socket message for a payload sealed with a key.
+    fn sealed(topic: &Topic, key: &Key, payload: &Value) -> String {
+        serde_json::to_string(&SocketMessage {
+            topic: topic.clone(),
+            kind: SocketMessageKind::Pub,
+            payload: Some(key.seal(payload.to_string()).unwrap()),
+            silent: true,
+        })
+        .unwrap()
+    }
+
+    /// Returns options for the stand-in bridge, along with the session on
+    /// chain 1 that was saved for them.
+    pub fn saved_session(bridge: &Bridge, connected: bool) -> (Options, Session) {
+        let store = Arc::new(MemoryStore::new());
+        let options = Options {
+            connection: Connection::Bridge(bridge.url.clone()),
+            ..options(store.clone())
+        };
+        let session = Session {
+            connected,
+            accounts: vec![Address::repeat_byte(1)],
+            chain_id: Some(1),
+            peer_id: Some(Topic::new()),
+            ..options.clone().create_session()
+        };
+        store.save(&options.profile, &session).unwrap();
+
+        (options, session)
+    }
+
+    /// Creates a client for a session on chain 1 with the stand-in bridge.
+    pub fn connect(bridge: &Bridge, connected: bool) -> (Client, Session) {
+        let (options, session) = saved_session(bridge, connected);
+        (Client::with_options(options).unwrap(), session)
+    }
+
+    #[test]
+    fn applies_session_updates() {
+        let bridge = Bridge::new();
+        let (client, session) = connect(&bridge, true);
+        let mut updates = client.session_updates();
+        assert_eq!(
+            executor::block_on(updates.next()).unwrap(),
+            SessionState::from(&session),
+        );
+
+        bridge.update(&session, true, 100);
+        assert_eq!(
+            executor::block_on(updates.next()).unwrap(),
+            SessionState {
+                connected: true,
+                accounts: session.accounts.clone(),
+                chain_id: 100,
+            },
+        );
+        assert_eq!(client.accounts().unwrap(), (session.accounts, 100));
+    }
+
+    #[test]
+    fn ignores_invalid_messages() {
+        let bridge = Bridge::new();
+        let (client, session) = connect(&bridge, true);
+        let mut updates = client.session_updates();
+        executor::block_on(updates.next()).unwrap();
+
+        bridge.publish(&session.client_id, &Key::random(), json!({}));
+        bridge.publish(&session.client_id, &session.key, json!("invalid"));
+        bridge.update(&session, true, 100);
+        assert_eq!(executor::block_on(updates.next()).unwrap().chain_id, 100);
+    }
+}
@@ -1,65 +1,160 @@
+use super::manager::{Route, Routes};
 use super::options::{Connection, Options};
-use super::session::Session;
+use super::session::{Session, SessionState};
 use super::socket::{MessageHandler, Socket, SocketError, SocketHandle};
-use super::storage::Storage;
-use crate::protocol::{Topic, Transaction};
+use super::storage::{Storage, StorageError};
+use crate::crypto::Key;
+use crate::errors::RpcErrorKind;
+use crate::protocol::{Metadata, Topic, Transaction};
 use crate::uri::Uri;
-use ethers_core::types::{Address, Bytes, H256};
-use futures::channel::oneshot;
+use ethers_core::types::{Address, Bytes, SignatureError, H256};
+use futures::channel::{mpsc, oneshot};
+use futures::future::{self, Either, FutureExt};
+use futures_timer::Delay;
 use jsonrpc_core::{Id, MethodCall, Output, Params, Version};
+use log::{debug, warn};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json::{json, Value};
 use std::collections::HashMap;
+use std::ops::Deref;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
 use thiserror::Error;
+use url::Url;
 
 #[derive(Debug)]
 pub struct Connector {
     current_request: AtomicU64,
     context: SharedContext,
-    socket: Socket,
+    socket: SocketRef,
+    max_age: Option<Duration>,
+    probe_timeout: Option<Duration>,
 }
 
-impl Connector {
-    pub fn new(options: Options) -> Result<Self, ConnectorError> {
+/// The bridge socket used by a connector, which is either owned by the
+/// connector or shared with other sessions through a session manager.
+#[derive(Debug)]
+enum SocketRef {
+    Owned(Socket),
+    Shared(Route),
+}
+
+impl SocketRef {
+    /// Subscribes to messages for the session published to a topic.
+    fn subscribe(&self, topic: Topic, key: Key) -> Result<(), SocketError> {
+        match self {
+            SocketRef::Owned(socket) => socket.subscribe(topic, key),
+            SocketRef::Shared(route) => route.subscribe(topic, key),
+        }
+    }
+}
+
+impl Deref for SocketRef {
+    type Target = Socket;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            SocketRef::Owned(socket) => socket,
+            SocketRef::Shared(route) => route.socket(),
+        }
+    }
+}
+
+/// The parameters for connecting a session to a bridge socket.
+struct Setup {
+    url: Url,
+    context: SharedContext,
+    topics: Vec<Topic>,
+    max_age: Option<Duration>,
+    probe_timeout: Option<Duration>,
+}
+
+impl Setup {
+    fn new(options: Options) -> Result<Self, ConnectorError> {
         let handshake_topic = match &options.connection {
             Connection::Uri(uri) => Some(uri.handshake_topic().clone()),
             _ => None,
         };
-        let session = Storage::for_session(options);
-        let client_id = session.client_id.clone();
-
-        // NOTE: WalletConnect bridge URLs are expected to be automatically
-        // converted from a `http(s)` to `ws(s)` protocol for the WebSocket
-        // connection.
-        let mut url = session.bridge.clone();
-        match url.scheme() {
-            "http" => url.set_scheme("ws").unwrap(),
-            "https" => url.set_scheme("wss").unwrap(),
-            "ws" | "wss" => {}
-            scheme => return Err(ConnectorError::BadScheme(scheme.into())),
-        }
+        let (max_age, probe_timeout) = (options.max_age, options.probe_timeout);
+        let session = Storage::for_session(options)?;
+        let url = bridge_socket_url(&session.bridge)?;
+        let topics = Some(session.client_id.clone())
+            .into_iter()
+            .chain(handshake_topic)
+            .collect();
+
+        Ok(Setup {
+            url,
+            context: SharedContext::new(session),
+            topics,
+            max_age,
+            probe_timeout,
+        })
+    }
 
-        let key = session.key.clone();
-        let context = SharedContext::new(session);
-        let handler = ConnectorHandler {
-            context: context.clone(),
-        };
+    fn handler(&self) -> ConnectorHandler {
+        ConnectorHandler {
+            context: self.context.clone(),
+        }
+    }
 
-        let socket = Socket::connect(url, key, handler)?;
-        socket.subscribe(client_id)?;
-        if let Some(handshake_topic) = handshake_topic {
-            socket.subscribe(handshake_topic)?;
+    fn finish(self, socket: SocketRef) -> Result<Connector, ConnectorError> {
+        let key = self.context.lock().session.key.clone();
+        for topic in self.topics {
+            socket.subscribe(topic, key.clone())?;
         }
 
         Ok(Connector {
             current_request: AtomicU64::default(),
-            context,
+            context: self.context,
             socket,
+            max_age: self.max_age,
+            probe_timeout: self.probe_timeout,
         })
     }
+}
+
+/// Returns the WebSocket URL for a WalletConnect bridge.
+pub fn bridge_socket_url(bridge: &Url) -> Result<Url, ConnectorError> {
+    // NOTE: WalletConnect bridge URLs are expected to be automatically
+    // converted from a `http(s)` to `ws(s)` protocol for the WebSocket
+    // connection.
+    let mut url = bridge.clone();
+    match url.scheme() {
+        "http" => url.set_scheme("ws").unwrap(),
+        "https" => url.set_scheme("wss").unwrap(),
+        "ws" | "wss" => {}
+        scheme => return Err(ConnectorError::BadScheme(scheme.into())),
+    }
+
+    Ok(url)
+}
+
+impl Connector {
+    pub fn new(options: Options) -> Result<Self, ConnectorError> {
+        let setup = Setup::new(options)?;
+        let socket = Socket::connect(setup.url.clone(), setup.handler())?;
+        setup.finish(SocketRef::Owned(socket))
+    }
+
+    /// Creates a new connector for a session that shares an existing bridge
+    /// socket with other sessions.
+    pub fn shared(
+        options: Options,
+        socket: &Arc<Socket>,
+        url: &Url,
+        routes: &Routes,
+    ) -> Result<Self, ConnectorError> {
+        let setup = Setup::new(options)?;
+        if setup.url != *url {
+            return Err(ConnectorError::BridgeMismatch(setup.url));
+        }
+
+        let route = routes.add(socket.clone(), setup.topics.clone(), setup.handler());
+        setup.finish(SocketRef::Shared(route))
+    }
 
     pub fn accounts(&self) -> Result<(Vec<Address>, u64), NotConnectedError> {
         let session = &self.context.lock().session;
@@ -73,6 +168,21 @@ impl Connector {
         ))
     }
 
+    pub fn metadata(&self) -> Metadata {
+        self.context.lock().session.client_meta.clone()
+    }
+
+    /// Subscribes to session state changes. The receiver first yields the
+    /// current session state, followed by the new state after every change to
+    /// the session's connection, accounts or chain.
+    pub fn subscribe(&self) -> mpsc::UnboundedReceiver<SessionState> {
+        let (tx, rx) = mpsc::unbounded();
+        let mut context = self.context.lock();
+        let _ = tx.unbounded_send(SessionState::from(&*context.session));
+        context.subscribers.push(tx);
+        rx
+    }
+
     async fn call<P, R>(&self, method: &str, params: P) -> Result<R, CallError>
     where
         P: Serialize,
@@ -80,14 +190,15 @@ impl Connector {
     {
         let id = self.current_request.fetch_add(1, Ordering::SeqCst);
 
-        let topic = {
+        let (topic, key) = {
             let context = self.context.lock();
-            context
+            let topic = context
                 .session
                 .peer_id
                 .clone()
-                .unwrap_or_else(|| context.session.handshake_topic.clone())
+                .unwrap_or_else(|| context.session.handshake_topic.clone());
             //.ok_or(CallError::NotConnected)?
+            (topic, context.session.key.clone())
         };
         let payload = {
             let params = match json!(params) {
@@ -110,6 +221,7 @@ impl Connector {
             | "eth_signTypedData"
             | "eth_signTypedData_v1"
             | "eth_signTypedData_v3"
+            | "eth_signTypedData_v4"
             | "personal_sign" => false,
             _ => true,
         };
@@ -124,23 +236,11 @@ impl Connector {
         // make sure we don't accidentially poison the mutex.
         debug_assert!(existing.is_none(), "request IDs should never collide",);
 
-        if let Err(err) = self.socket.publish(topic, payload, silent) {
-            // NOTE: Remove the request from the pending request map if we were
-            // unable to send it as there will never be a response.
-            let removed = {
-                let mut context = self.context.lock();
-                context.pending_requests.remove(&Id::Num(id))
-            };
-
-            // NOTE: Make sure panic is always outside the mutex guard's
-            // scope to make sure we don't accidentially poison the mutex.
-            debug_assert!(
-                removed.is_some(),
-                "immediately removed request should never be missing"
-            );
-
-            return Err(err.into());
-        }
+        // NOTE: Remove the request from the pending request map if it is
+        // dropped before receiving a response, either because we were unable
+        // to send it or because it timed out, as it will never be answered.
+        let _pending = PendingRequest(&self.context, Id::Num(id));
+        self.socket.publish(topic, &key, payload, silent)?;
 
         let response = rx.await?;
         match response {
@@ -156,16 +256,15 @@ impl Connector {
     where
         F: FnOnce(Uri),
     {
-        let uri = {
+        if self.check_session().await? {
             let context = self.context.lock();
-            if context.session.connected {
-                return Ok((
-                    context.session.accounts.clone(),
-                    context.session.chain_id.unwrap_or_default(),
-                ));
-            }
-            context.session.uri()
-        };
+            return Ok((
+                context.session.accounts.clone(),
+                context.session.chain_id.unwrap_or_default(),
+            ));
+        }
+
+        let uri = self.context.lock().session.uri();
 
         f(uri);
         let (accounts, chain_id) = self.create_session().await?;
@@ -173,6 +272,68 @@ impl Connector {
         Ok((accounts, chain_id))
     }
 
+    /// Checks whether the current session can still be used, replacing it
+    /// with a new session if it has expired or the peer is no longer
+    /// responding.
+    async fn check_session(&self) -> Result<bool, SessionError> {
+        {
+            let context = self.context.lock();
+            if !context.session.connected {
+                return Ok(false);
+            }
+            if let Some(max_age) = self.max_age {
+                if context.session.is_expired(max_age) {
+                    debug!("session expired, pairing a new one");
+                    drop(context);
+                    self.reset_session()?;
+                    return Ok(false);
+                }
+            }
+        }
+
+        if let Some(timeout) = self.probe_timeout {
+            if !self.probe(timeout).await? {
+                debug!("peer did not respond to liveness probe, pairing a new session");
+                self.reset_session()?;
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Replaces the current session with a new one, so that a new session is
+    /// paired with a fresh key and topics instead of those of the old one.
+    fn reset_session(&self) -> Result<(), SessionError> {
+        let (client_id, key) = {
+            let mut context = self.context.lock();
+            context.reset()?;
+            (
+                context.session.client_id.clone(),
+                context.session.key.clone(),
+            )
+        };
+        self.socket
+            .subscribe(client_id, key)
+            .map_err(CallError::from)?;
+
+        Ok(())
+    }
+
+    /// Probes the peer for liveness, returning `false` if it did not respond
+    /// within the specified timeout.
+    ///
+    /// Note that any response, including a JSON RPC error, indicates that the
+    /// peer is alive.
+    pub async fn probe(&self, timeout: Duration) -> Result<bool, CallError> {
+        let request = self.call::<_, Value>("eth_chainId", Vec::<Value>::new());
+        match future::select(request.boxed(), Delay::new(timeout)).await {
+            Either::Left((Ok(_), _)) | Either::Left((Err(CallError::Rpc(_)), _)) => Ok(true),
+            Either::Left((Err(err), _)) => Err(err),
+            Either::Right(_) => Ok(false),
+        }
+    }
+
     pub async fn create_session(&self) -> Result<(Vec<Address>, u64), SessionError> {
         let params = {
             let mut context = self.context.lock();
@@ -187,18 +348,19 @@ impl Connector {
             context.session.request()
         };
 
+        // NOTE: Signal that the session is no longer pending even if the
+        // request is dropped before completing, for example on a timeout.
+        let pending = PendingSession(&self.context);
         let result = self.call("wc_sessionRequest", params).await;
+        drop(pending);
 
         let (accounts, chain_id) = {
             let mut context = self.context.lock();
-            context.session_pending = false;
 
             // NOTE: Propagate the error only after updating signaling that the
             // session is no longer pending.
             let session_params = result?;
-            context
-                .session
-                .update(move |session| session.apply(session_params));
+            context.update(move |session| session.apply(session_params))?;
 
             (
                 context.session.accounts.clone(),
@@ -224,9 +386,26 @@ impl Connector {
         self.call("personal_sign", data).await
     }
 
-    // pub fn sign_message() {}
-    // pub fn signed_typed_data() {}
-    // pub fn send_custom_request() {}
+    pub async fn sign_message(&self, address: Address, data: &str) -> Result<Bytes, CallError> {
+        self.call("eth_sign", (address, data)).await
+    }
+
+    pub async fn sign_typed_data(
+        &self,
+        address: Address,
+        typed_data: &Value,
+    ) -> Result<Bytes, CallError> {
+        self.call("eth_signTypedData", (address, typed_data.to_string()))
+            .await
+    }
+
+    pub async fn send_custom_request(
+        &self,
+        method: &str,
+        params: Vec<Value>,
+    ) -> Result<Value, CallError> {
+        self.call(method, params).await
+    }
 
     // pub fn approve_session() {}
     // pub fn reject_session() {}
@@ -234,7 +413,15 @@ impl Connector {
     // pub fn reject_request() {}
 
     pub fn close(self) -> Result<(), SocketError> {
-        self.socket.close()
+        match self.socket {
+            SocketRef::Owned(socket) => socket.close(),
+            // NOTE: Shared sockets are owned by the session manager, so only
+            // stop routing messages to this session.
+            SocketRef::Shared(route) => {
+                drop(route);
+                Ok(())
+            }
+        }
     }
 }
 
@@ -246,8 +433,12 @@ pub struct NotConnectedError;
 pub enum ConnectorError {
     #[error("invalid URL scheme '{0}', must be 'http(s)' or 'ws(s)'")]
     BadScheme(String),
+    #[error("session bridge '{0}' does not match the session manager's bridge")]
+    BridgeMismatch(Url),
     #[error("socket error: {0}")]
     SocketError(#[from] SocketError),
+    #[error("session storage error: {0}")]
+    Storage(#[from] StorageError),
 }
 
 #[derive(Debug, Error)]
@@ -262,6 +453,31 @@ pub enum CallError {
     Rpc(#[from] jsonrpc_core::Error),
     #[error("JSON serialization error: {0}")]
     Json(#[from] serde_json::Error),
+    #[error("invalid signature: {0}")]
+    Signature(#[from] SignatureError),
+}
+
+impl From<NotConnectedError> for CallError {
+    fn from(_: NotConnectedError) -> Self {
+        CallError::NotConnected
+    }
+}
+
+impl CallError {
+    /// Returns the classification of the wallet's JSON RPC error, if the call
+    /// failed with one.
+    pub fn rpc_error_kind(&self) -> Option<RpcErrorKind> {
+        match self {
+            CallError::Rpc(err) => Some(RpcErrorKind::of(err)),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the call failed because the user rejected it.
+    pub fn is_user_rejection(&self) -> bool {
+        self.rpc_error_kind()
+            .is_some_and(|kind| kind.is_user_rejection())
+    }
 }
 
 #[derive(Debug, Error)]
@@ -274,6 +490,8 @@ pub enum SessionError {
     Call(#[from] CallError),
     #[error("JSON serialization error: {0}")]
     Json(#[from] serde_json::Error),
+    #[error("session storage error: {0}")]
+    Storage(#[from] StorageError),
 }
 
 #[derive(Clone, Debug)]
@@ -281,26 +499,74 @@ struct SharedContext(Arc<Mutex<Context>>);
 
 #[derive(Debug)]
 struct Context {
-    session: Storage<Session>,
+    session: Storage,
     pending_requests: HashMap<Id, oneshot::Sender<Output>>,
     session_pending: bool,
+    subscribers: Vec<mpsc::UnboundedSender<SessionState>>,
+}
+
+impl Context {
+    /// Updates the session's connection state and notifies subscribers of the
+    /// new state.
+    fn update<F>(&mut self, f: F) -> Result<(), StorageError>
+    where
+        F: FnOnce(&mut Session),
+    {
+        // NOTE: The session is updated in memory even if it fails to save, so
+        // notify subscribers regardless.
+        let result = self.session.update(f);
+        self.notify();
+        result
+    }
+
+    /// Replaces the session with a new one and notifies subscribers that it
+    /// is no longer connected.
+    fn reset(&mut self) -> Result<(), StorageError> {
+        let result = self.session.reset();
+        self.notify();
+        result
+    }
+
+    fn notify(&mut self) {
+        let state = SessionState::from(&*self.session);
+        self.subscribers
+            .retain(|subscriber| subscriber.unbounded_send(state.clone()).is_ok());
+    }
 }
 
 impl SharedContext {
-    fn new(session: Storage<Session>) -> Self {
+    fn new(session: Storage) -> Self {
         SharedContext(Arc::new(Mutex::new(Context {
             session,
             pending_requests: HashMap::new(),
             session_pending: false,
+            subscribers: Vec::new(),
         })))
     }
 
-    fn lock(&self) -> MutexGuard<Context> {
+    fn lock(&self) -> MutexGuard<'_, Context> {
         self.0.lock().expect("mutex guard should never be poisoned")
     }
 }
 
-struct ConnectorHandler {
+struct PendingSession<'a>(&'a SharedContext);
+
+struct PendingRequest<'a>(&'a SharedContext, Id);
+
+impl Drop for PendingRequest<'_> {
+    fn drop(&mut self) {
+        self.0.lock().pending_requests.remove(&self.1);
+    }
+}
+
+impl Drop for PendingSession<'_> {
+    fn drop(&mut self) {
+        self.0.lock().session_pending = false;
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ConnectorHandler {
     context: SharedContext,
 }
 
@@ -311,11 +577,12 @@ impl MessageHandler for ConnectorHandler {
         if let Ok(request) = serde_json::from_str::<MethodCall>(&payload) {
             match request.method.as_str() {
                 "wc_sessionUpdate" => {
-                    let session_update = request.params.parse()?;
+                    // NOTE: Session updates are sent as a single positional parameter.
+                    let (session_update,) = request.params.parse()?;
                     let mut context = self.context.lock();
-                    context
-                        .session
-                        .update(|session| session.update(session_update));
+                    if let Err(err) = context.update(|session| session.update(session_update)) {
+                        warn!("failed to save session update: {}", err);
+                    }
                 }
                 _ => return Err(MessageError::UnsupportedRequest(payload)),
             }
@@ -335,6 +602,7 @@ impl MessageHandler for ConnectorHandler {
             // that it is not considered an error to drop the future that is
             // waiting for the response before it arrives.
             let _ = sender.send(response);
+            context.session.touch();
         }
 
         Ok(())
@@ -352,3 +620,91 @@ pub enum MessageError {
     #[error("JSON RPC error: {0}")]
     Rpc(#[from] jsonrpc_core::Error),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{saved_session, Bridge};
+    use crate::client::Client;
+    use futures::executor::block_on;
+
+    /// Creates a connector for a connected session on a stand-in bridge.
+    fn connect(responsive: bool, f: impl FnOnce(Options) -> Options) -> (Connector, Session) {
+        let bridge = Bridge::new();
+        let (options, session) = saved_session(&bridge, true);
+        if responsive {
+            bridge.respond(&session, json!("0x01"));
+        }
+
+        (Connector::new(f(options)).unwrap(), session)
+    }
+
+    fn assert_replaced(connector: &Connector, session: &Session) {
+        let context = connector.context.lock();
+        assert!(!context.session.connected);
+        assert_ne!(context.session.client_id, session.client_id);
+        assert_ne!(context.session.key, session.key);
+        assert_ne!(context.session.handshake_topic, session.handshake_topic);
+    }
+
+    #[test]
+    fn probes_live_peers() {
+        let (connector, session) = connect(true, |options| Options {
+            probe_timeout: Some(Duration::from_secs(10)),
+            ..options
+        });
+        assert!(block_on(connector.probe(Duration::from_secs(10))).unwrap());
+        assert!(block_on(connector.check_session()).unwrap());
+        assert_eq!(
+            connector.context.lock().session.client_id,
+            session.client_id
+        );
+    }
+
+    #[test]
+    fn replaces_unresponsive_sessions() {
+        let (connector, session) = connect(false, |options| Options {
+            probe_timeout: Some(Duration::from_millis(100)),
+            ..options
+        });
+        assert!(!block_on(connector.check_session()).unwrap());
+        assert_replaced(&connector, &session);
+    }
+
+    #[test]
+    fn forgets_timed_out_requests() {
+        let (connector, _) = connect(false, |options| options);
+        assert!(!block_on(connector.probe(Duration::from_millis(100))).unwrap());
+        assert!(connector.context.lock().pending_requests.is_empty());
+    }
+
+    #[test]
+    fn replaces_expired_sessions() {
+        let (connector, session) = connect(false, |options| Options {
+            max_age: Some(Duration::from_secs(60)),
+            ..options
+        });
+        assert!(block_on(connector.check_session()).unwrap());
+
+        connector
+            .context
+            .lock()
+            .session
+            .update(|session| session.created_at -= 3600)
+            .unwrap();
+        assert!(!block_on(connector.check_session()).unwrap());
+        assert_replaced(&connector, &session);
+    }
+
+    #[test]
+    fn rejects_invalid_personal_signatures() {
+        let (connector, _) = connect(true, |options| options);
+        let client = Client {
+            connection: connector,
+        };
+        assert!(matches!(
+            block_on(client.personal_sign(&["0x", "0x"])),
+            Err(CallError::Signature(_))
+        ));
+    }
+}
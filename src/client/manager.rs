@@ -0,0 +1,361 @@
+use super::core::{bridge_socket_url, Connector, ConnectorError, ConnectorHandler};
+use super::options::{Connection, Options, DEFAULT_BRIDGE_URL};
+use super::socket::{MessageHandler, Socket, SocketError, SocketHandle};
+use super::Client;
+use crate::crypto::Key;
+use crate::protocol::Topic;
+use log::warn;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use url::Url;
+
+/// A manager for many concurrent WalletConnect sessions that share a single
+/// bridge connection.
+///
+/// Incoming messages are routed to sessions by topic, and each session is
+/// exposed as a regular [`Client`]. Closing or dropping a managed client only
+/// stops routing messages to it; the bridge connection stays open until the
+/// manager is closed.
+#[derive(Debug)]
+pub struct SessionManager {
+    bridge: Url,
+    url: Url,
+    socket: Arc<Socket>,
+    routes: Routes,
+}
+
+impl SessionManager {
+    pub fn new() -> Result<Self, ConnectorError> {
+        SessionManager::with_bridge(DEFAULT_BRIDGE_URL.clone())
+    }
+
+    pub fn with_bridge(bridge: Url) -> Result<Self, ConnectorError> {
+        let url = bridge_socket_url(&bridge)?;
+        let routes = Routes::default();
+        let socket = Socket::connect(
+            url.clone(),
+            Router {
+                routes: routes.clone(),
+            },
+        )?;
+
+        Ok(SessionManager {
+            bridge,
+            url,
+            socket: Arc::new(socket),
+            routes,
+        })
+    }
+
+    pub fn bridge(&self) -> &Url {
+        &self.bridge
+    }
+
+    /// Creates a client for a session on the manager's bridge.
+    ///
+    /// Sessions created with [`Connection::Bridge`] options use the manager's
+    /// bridge, while sessions created from a URI must share the same bridge
+    /// as the manager.
+    pub fn client(&self, mut options: Options) -> Result<Client, ConnectorError> {
+        if let Connection::Bridge(_) = options.connection {
+            options.connection = Connection::Bridge(self.bridge.clone());
+        }
+
+        Ok(Client {
+            connection: Connector::shared(options, &self.socket, &self.url, &self.routes)?,
+        })
+    }
+
+    /// Returns the number of sessions currently managed.
+    pub fn len(&self) -> usize {
+        self.routes.sessions()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Closes the bridge connection for all managed sessions.
+    pub fn close(self) -> Result<(), SocketError> {
+        match Arc::try_unwrap(self.socket) {
+            Ok(socket) => socket.close(),
+            // NOTE: Managed clients are still holding on to the socket, so
+            // shut it down without waiting for the event loop to finish.
+            Err(socket) => socket.shutdown(),
+        }
+    }
+}
+
+/// The routing table from subscribed topics to session message handlers.
+#[derive(Debug)]
+pub struct Routes<H = ConnectorHandler>(Arc<Mutex<RoutingTable<H>>>);
+
+#[derive(Debug)]
+struct RoutingTable<H> {
+    handlers: HashMap<Topic, H>,
+    sessions: usize,
+}
+
+impl<H> Clone for Routes<H> {
+    fn clone(&self) -> Self {
+        Routes(self.0.clone())
+    }
+}
+
+impl<H> Default for Routes<H> {
+    fn default() -> Self {
+        Routes(Arc::new(Mutex::new(RoutingTable {
+            handlers: HashMap::new(),
+            sessions: 0,
+        })))
+    }
+}
+
+impl<H> Routes<H>
+where
+    H: Clone,
+{
+    fn lock(&self) -> MutexGuard<'_, RoutingTable<H>> {
+        self.0.lock().expect("mutex guard should never be poisoned")
+    }
+
+    pub fn add(&self, socket: Arc<Socket>, topics: Vec<Topic>, handler: H) -> Route<H> {
+        let mut table = self.lock();
+        for topic in &topics {
+            table.handlers.insert(topic.clone(), handler.clone());
+        }
+        table.sessions += 1;
+
+        Route {
+            socket,
+            routes: self.clone(),
+            handler,
+            topics: Mutex::new(topics),
+        }
+    }
+
+    fn get(&self, topic: &Topic) -> Option<H> {
+        self.lock().handlers.get(topic).cloned()
+    }
+
+    fn sessions(&self) -> usize {
+        self.lock().sessions
+    }
+}
+
+/// A registered route for a single session, removed when dropped.
+#[derive(Debug)]
+pub struct Route<H = ConnectorHandler>
+where
+    H: Clone,
+{
+    socket: Arc<Socket>,
+    routes: Routes<H>,
+    handler: H,
+    topics: Mutex<Vec<Topic>>,
+}
+
+impl<H> Route<H>
+where
+    H: Clone,
+{
+    pub fn socket(&self) -> &Socket {
+        &self.socket
+    }
+
+    /// Subscribes to an additional topic for the session, for example when
+    /// a new session is paired.
+    pub fn subscribe(&self, topic: Topic, key: Key) -> Result<(), SocketError> {
+        self.routes
+            .lock()
+            .handlers
+            .insert(topic.clone(), self.handler.clone());
+        self.topics
+            .lock()
+            .expect("mutex guard should never be poisoned")
+            .push(topic.clone());
+        self.socket.subscribe(topic, key)
+    }
+}
+
+impl<H> Drop for Route<H>
+where
+    H: Clone,
+{
+    fn drop(&mut self) {
+        let mut table = self.routes.lock();
+        let topics = self
+            .topics
+            .get_mut()
+            .expect("mutex guard should never be poisoned");
+        for topic in topics.iter() {
+            table.handlers.remove(topic);
+            self.socket.unsubscribe(topic);
+        }
+        table.sessions -= 1;
+    }
+}
+
+struct Router<H> {
+    routes: Routes<H>,
+}
+
+impl<H> MessageHandler for Router<H>
+where
+    H: MessageHandler + Clone,
+{
+    type Err = H::Err;
+
+    fn message(
+        &mut self,
+        socket: SocketHandle,
+        topic: Topic,
+        payload: String,
+    ) -> Result<(), H::Err> {
+        let mut handler = match self.routes.get(&topic) {
+            Some(handler) => handler,
+            None => {
+                warn!("ignoring message for unrouted topic '{}'", topic);
+                return Ok(());
+            }
+        };
+
+        // NOTE: Errors handling a message for a single session should not
+        // close the bridge connection for all other sessions.
+        if let Err(err) = handler.message(socket, topic.clone(), payload) {
+            warn!("error handling message for topic '{}': {}", topic, err);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{saved_session, Bridge};
+    use futures::executor::block_on;
+    use serde_json::json;
+    use std::convert::Infallible;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// A stand-in bridge that echoes published messages back to the socket
+    /// that published them.
+    fn echo_bridge() -> Url {
+        let server = parity_ws::WebSocket::new(|out: parity_ws::Sender| {
+            move |message: parity_ws::Message| {
+                if message.as_text()?.contains(r#""type":"pub""#) {
+                    out.send(message)?;
+                }
+                Ok(())
+            }
+        })
+        .unwrap()
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let url = format!("ws://{}", server.local_addr().unwrap());
+        thread::spawn(move || server.run());
+        url.parse().unwrap()
+    }
+
+    /// A handler that records the payloads that were routed to it.
+    #[derive(Clone, Debug, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Recorder {
+        fn received(&self) -> Vec<String> {
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    impl MessageHandler for Recorder {
+        type Err = Infallible;
+
+        fn message(
+            &mut self,
+            _: SocketHandle,
+            _: Topic,
+            payload: String,
+        ) -> Result<(), Infallible> {
+            self.0.lock().unwrap().push(payload);
+            Ok(())
+        }
+    }
+
+    fn wait_for(condition: impl Fn() -> bool) {
+        let start = Instant::now();
+        while !condition() {
+            assert!(start.elapsed() < Duration::from_secs(10), "timed out");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn routes_messages_by_topic() {
+        let routes = Routes::<Recorder>::default();
+        let socket = Arc::new(
+            Socket::connect(
+                echo_bridge(),
+                Router {
+                    routes: routes.clone(),
+                },
+            )
+            .unwrap(),
+        );
+
+        let (a, b) = (Recorder::default(), Recorder::default());
+        let (topic_a, topic_b) = (Topic::new(), Topic::new());
+        let (key_a, key_b) = (Key::random(), Key::random());
+        let route_a = routes.add(socket.clone(), vec![], a.clone());
+        route_a.subscribe(topic_a.clone(), key_a.clone()).unwrap();
+        let route_b = routes.add(socket.clone(), vec![], b.clone());
+        route_b.subscribe(topic_b.clone(), key_b.clone()).unwrap();
+        assert_eq!(routes.sessions(), 2);
+
+        // NOTE: Messages that can't be opened are dropped without affecting
+        // other messages on the socket.
+        socket
+            .publish(topic_a.clone(), &key_b, "junk", true)
+            .unwrap();
+        socket.publish(topic_a.clone(), &key_a, "a", true).unwrap();
+        socket.publish(topic_b.clone(), &key_b, "b", true).unwrap();
+        wait_for(|| !a.received().is_empty() && !b.received().is_empty());
+        assert_eq!(a.received(), ["a"]);
+        assert_eq!(b.received(), ["b"]);
+
+        drop(route_a);
+        assert_eq!(routes.sessions(), 1);
+        assert!(routes.get(&topic_a).is_none());
+
+        socket.publish(topic_a, &key_a, "unrouted", true).unwrap();
+        socket.publish(topic_b, &key_b, "b", true).unwrap();
+        wait_for(|| b.received().len() == 2);
+        assert_eq!(a.received(), ["a"]);
+    }
+
+    #[test]
+    fn manages_sessions_on_one_socket() {
+        let bridge = Bridge::new();
+        let manager = SessionManager::with_bridge(bridge.url.clone()).unwrap();
+        let clients = ["0x01", "0x02"]
+            .into_iter()
+            .map(|result| {
+                let (options, session) = saved_session(&bridge, true);
+                bridge.respond(&session, json!(result));
+                (manager.client(options).unwrap(), result)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(manager.len(), 2);
+
+        for (client, result) in &clients {
+            assert_eq!(
+                block_on(client.send_custom_request("eth_chainId", vec![])).unwrap(),
+                json!(result),
+            );
+        }
+
+        drop(clients);
+        assert!(manager.is_empty());
+    }
+}
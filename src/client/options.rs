@@ -1,9 +1,12 @@
-use super::session::Session;
+use super::session::{unix_timestamp, Session};
+use super::storage::{FileStore, SessionStore};
 use crate::crypto::Key;
 use crate::protocol::{Metadata, Topic};
 use crate::uri::Uri;
 use lazy_static::lazy_static;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use url::Url;
 
 lazy_static! {
@@ -29,6 +32,14 @@ pub struct Options {
     pub meta: Metadata,
     pub connection: Connection,
     pub chain_id: Option<u64>,
+    pub store: Arc<dyn SessionStore>,
+    /// The maximum age of a session before it is considered expired and a new
+    /// one is paired.
+    pub max_age: Option<Duration>,
+    /// When set, connected sessions are probed for liveness when ensuring a
+    /// session, and a new session is paired if the peer does not respond
+    /// within this timeout.
+    pub probe_timeout: Option<Duration>,
 }
 
 impl Options {
@@ -38,6 +49,9 @@ impl Options {
             meta,
             connection: Connection::default(),
             chain_id: None,
+            store: Arc::new(FileStore::default()),
+            max_age: None,
+            probe_timeout: None,
         }
     }
 
@@ -47,6 +61,18 @@ impl Options {
             meta,
             connection: Connection::Uri(uri),
             chain_id: None,
+            store: Arc::new(FileStore::default()),
+            max_age: None,
+            probe_timeout: None,
+        }
+    }
+
+    /// Persists sessions as files in the specified directory instead of the
+    /// default cache directory.
+    pub fn with_storage_dir(self, dir: impl Into<PathBuf>) -> Self {
+        Options {
+            store: Arc::new(FileStore::new(dir)),
+            ..self
         }
     }
 
@@ -57,6 +83,7 @@ impl Options {
             Connection::Uri(uri) => uri.into_parts(),
         };
         let chain_id = self.chain_id;
+        let now = unix_timestamp();
 
         Session {
             connected: false,
@@ -70,14 +97,29 @@ impl Options {
             peer_meta: None,
             handshake_id: 0,
             handshake_topic,
+            created_at: now,
+            last_seen: now,
         }
     }
 
+    pub fn is_expired(&self, session: &Session) -> bool {
+        self.max_age
+            .is_some_and(|max_age| session.is_expired(max_age))
+    }
+
     pub fn matches(&self, session: &Session) -> bool {
         self.meta == session.client_meta
             && match &self.connection {
                 Connection::Bridge(bridge) => *bridge == session.bridge,
-                Connection::Uri(uri) => *uri == session.uri(),
+                // NOTE: A different URI is a new pairing for the same client,
+                // see `Options::is_new_pairing`.
+                Connection::Uri(_) => true,
             }
     }
+
+    /// Returns true if the options pair with a different URI than the one
+    /// the session was created for, in which case a new session is started.
+    pub fn is_new_pairing(&self, session: &Session) -> bool {
+        matches!(&self.connection, Connection::Uri(uri) if *uri != session.uri())
+    }
 }
@@ -0,0 +1,214 @@
+use super::session::Session;
+use super::storage::{
+    Encryption, FileStore, ProfileDocument, ProfileKey, SessionStore, StorageError,
+};
+use crate::protocol::PeerMetadata;
+use data_encoding::BASE64URL_NOPAD as BASE64;
+use ethers_core::types::Address;
+use log::warn;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use url::Url;
+use zeroize::Zeroizing;
+
+/// Management of the session profiles persisted in a session store.
+#[derive(Clone, Debug)]
+pub struct Profiles {
+    store: Arc<dyn SessionStore>,
+}
+
+/// A summary of a stored session profile.
+#[derive(Clone, Debug)]
+pub struct ProfileInfo {
+    pub profile: PathBuf,
+    pub connected: bool,
+    pub accounts: Vec<Address>,
+    pub chain_id: Option<u64>,
+    pub bridge: Url,
+    pub peer_meta: Option<PeerMetadata>,
+}
+
+impl ProfileInfo {
+    fn new(profile: PathBuf, session: &Session) -> Self {
+        ProfileInfo {
+            profile,
+            connected: session.connected,
+            accounts: session.accounts.clone(),
+            chain_id: session.chain_id,
+            bridge: session.bridge.clone(),
+            peer_meta: session.peer_meta.clone(),
+        }
+    }
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        Profiles::new(Arc::new(FileStore::default()))
+    }
+}
+
+impl Profiles {
+    pub fn new(store: Arc<dyn SessionStore>) -> Self {
+        Profiles { store }
+    }
+
+    /// Lists all stored profiles.
+    ///
+    /// Profiles that can't be read, for example because they are corrupt or
+    /// encrypted with a different key, are skipped with a warning. They can
+    /// still be inspected individually to find out why.
+    pub fn list(&self) -> Result<Vec<ProfileInfo>, StorageError> {
+        let mut profiles = Vec::new();
+        for profile in self.store.profiles()? {
+            match self.inspect(&profile) {
+                Ok(Some(info)) => profiles.push(info),
+                Ok(None) => {}
+                Err(err) => warn!("skipping profile '{}': {}", profile.display(), err),
+            }
+        }
+
+        Ok(profiles)
+    }
+
+    /// Retrieves information on a single stored profile.
+    pub fn inspect(&self, profile: impl AsRef<Path>) -> Result<Option<ProfileInfo>, StorageError> {
+        let profile = profile.as_ref();
+        let session = self.store.load(profile)?;
+        Ok(session.map(|session| ProfileInfo::new(profile.to_owned(), &session)))
+    }
+
+    /// Deletes a stored profile. This fails if the profile is currently in use
+    /// by a client.
+    pub fn delete(&self, profile: impl AsRef<Path>) -> Result<(), StorageError> {
+        let profile = profile.as_ref();
+        // NOTE: The profile is deleted while it is locked, so that stores can
+        // remove their lock files along with it.
+        let _lock = self.store.lock(profile)?;
+        self.store.delete(profile)
+    }
+
+    /// Exports a stored profile's session as a portable blob that can be
+    /// imported into another session store.
+    ///
+    /// Since the blob contains the session's symmetric key, it can optionally
+    /// be encrypted with a profile key.
+    pub fn export(
+        &self,
+        profile: impl AsRef<Path>,
+        key: Option<ProfileKey>,
+    ) -> Result<String, StorageError> {
+        let profile = profile.as_ref();
+        let session = self
+            .store
+            .load(profile)?
+            .ok_or_else(|| StorageError::NotFound(profile.to_owned()))?;
+
+        let encryption = key.map(Encryption::new).transpose()?;
+        let document = ProfileDocument::seal(&session, encryption.as_ref())?;
+        let json = Zeroizing::new(serde_json::to_vec(&document)?);
+
+        Ok(BASE64.encode(&json))
+    }
+
+    /// Imports a session blob created with [`Profiles::export`] as a new
+    /// profile.
+    pub fn import(
+        &self,
+        profile: impl AsRef<Path>,
+        blob: impl AsRef<str>,
+        key: Option<ProfileKey>,
+    ) -> Result<ProfileInfo, StorageError> {
+        let profile = profile.as_ref();
+        let json = Zeroizing::new(BASE64.decode(blob.as_ref().trim().as_bytes())?);
+        let encryption = key.map(Encryption::new).transpose()?;
+        let (session, _) = ProfileDocument::open(&json, encryption.as_ref())?;
+
+        let _lock = self.store.lock(profile)?;
+        if self.store.load(profile)?.is_some() {
+            return Err(StorageError::Exists(profile.to_owned()));
+        }
+        self.store.save(profile, &session)?;
+
+        Ok(ProfileInfo::new(profile.to_owned(), &session))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::options;
+    use crate::client::MemoryStore;
+
+    fn session() -> Session {
+        options(Arc::new(MemoryStore::new())).create_session()
+    }
+
+    #[test]
+    fn list_and_delete_profiles() {
+        let store = Arc::new(MemoryStore::new());
+        store.save(Path::new("a"), &session()).unwrap();
+        store.save(Path::new("b"), &session()).unwrap();
+
+        let profiles = Profiles::new(store);
+        let listed = profiles
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|info| info.profile)
+            .collect::<Vec<_>>();
+        assert_eq!(listed, [PathBuf::from("a"), PathBuf::from("b")]);
+
+        profiles.delete("a").unwrap();
+        assert!(profiles.inspect("a").unwrap().is_none());
+        assert!(profiles.inspect("b").unwrap().is_some());
+    }
+
+    #[test]
+    fn delete_removes_lock_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = Arc::new(FileStore::new(dir.path()));
+        store.save(Path::new("a"), &session()).unwrap();
+
+        Profiles::new(store).delete("a").unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn list_skips_unreadable_profiles() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = Arc::new(FileStore::new(dir.path()));
+        store.save(Path::new("a"), &session()).unwrap();
+        std::fs::write(dir.path().join("b.json"), "not a session").unwrap();
+
+        let profiles = Profiles::new(store);
+        let listed = profiles.list().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].profile, PathBuf::from("a"));
+        assert!(matches!(profiles.inspect("b"), Err(StorageError::Json(_))));
+    }
+
+    #[test]
+    fn export_and_import_profile() {
+        let session = session();
+        let source = Profiles::new(Arc::new(MemoryStore::new()));
+        source.store.save(Path::new("a"), &session).unwrap();
+
+        let key = ProfileKey::raw([42; 32]);
+        let blob = source.export("a", Some(key.clone())).unwrap();
+
+        let destination = Profiles::new(Arc::new(MemoryStore::new()));
+        assert!(matches!(
+            destination.import("b", &blob, None),
+            Err(StorageError::MissingKey)
+        ));
+        destination.import("b", &blob, Some(key.clone())).unwrap();
+        assert!(matches!(
+            destination.import("b", &blob, Some(key)),
+            Err(StorageError::Exists(_))
+        ));
+
+        let imported = destination.store.load(Path::new("b")).unwrap().unwrap();
+        assert_eq!(imported.key, session.key);
+        assert_eq!(imported.client_id, session.client_id);
+    }
+}
@@ -4,12 +4,20 @@ use crate::protocol::{
 };
 use crate::uri::Uri;
 use ethers_core::types::Address;
+use serde::de::{self, Deserializer};
+use serde::ser::{self, Serializer};
 use serde::{Deserialize, Serialize};
-use url::form_urlencoded::Serializer;
+use serde_json::Value;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use url::form_urlencoded::Serializer as FormSerializer;
 use url::Url;
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
+/// A WalletConnect session.
+///
+/// Sessions are serialized with a schema version so that sessions persisted
+/// by older versions of this crate are migrated when they are deserialized.
+#[derive(Clone, Debug)]
 pub struct Session {
     pub connected: bool,
     pub accounts: Vec<Address>,
@@ -22,14 +30,101 @@ pub struct Session {
     pub peer_meta: Option<PeerMetadata>,
     pub handshake_id: u64,
     pub handshake_topic: Topic,
+    /// The time the session was created, in seconds since the Unix epoch.
+    pub created_at: u64,
+    /// The last time the peer was heard from, in seconds since the Unix
+    /// epoch.
+    pub last_seen: u64,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(remote = "Session", rename_all = "camelCase")]
+struct SessionSchema {
+    connected: bool,
+    accounts: Vec<Address>,
+    chain_id: Option<u64>,
+    bridge: Url,
+    key: Key,
+    client_id: Topic,
+    client_meta: Metadata,
+    peer_id: Option<Topic>,
+    peer_meta: Option<PeerMetadata>,
+    handshake_id: u64,
+    handshake_topic: Topic,
+    created_at: u64,
+    last_seen: u64,
+}
+
+/// A migration from a session schema version to the next.
+type Migration = fn(&mut serde_json::Map<String, Value>) -> Result<(), SchemaError>;
+
+/// Session schema migrations, where the migration at index `i` upgrades a
+/// session from schema version `i` to version `i + 1`.
+const MIGRATIONS: &[Migration] = &[
+    // Version 0 sessions are unversioned sessions from before schema
+    // versioning was introduced. Their layout is otherwise unchanged.
+    |_| Ok(()),
+    // Version 2 adds session timestamps. The actual creation time of older
+    // sessions is unknown, so use the time of the migration instead.
+    |session| {
+        let now = Value::from(unix_timestamp());
+        session.insert("createdAt".into(), now.clone());
+        session.insert("lastSeen".into(), now);
+        Ok(())
+    },
+];
+
+/// Returns the current time in seconds since the Unix epoch.
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
 }
 
 impl Session {
+    /// The current session schema version.
+    pub const VERSION: u64 = MIGRATIONS.len() as _;
+
+    /// Upgrades a serialized session to the current schema version.
+    pub fn migrate(mut value: Value) -> Result<Value, SchemaError> {
+        let session = value.as_object_mut().ok_or(SchemaError::NotAnObject)?;
+        let version = match session.get("version") {
+            Some(version) => version.as_u64().ok_or(SchemaError::InvalidVersion)?,
+            None => 0,
+        };
+        if version > Session::VERSION {
+            return Err(SchemaError::Unsupported(version));
+        }
+
+        for migration in &MIGRATIONS[version as usize..] {
+            migration(session)?;
+        }
+        session.insert("version".into(), Session::VERSION.into());
+
+        Ok(value)
+    }
+
+    /// Returns the time elapsed since the session was created.
+    pub fn age(&self) -> Duration {
+        Duration::from_secs(unix_timestamp().saturating_sub(self.created_at))
+    }
+
+    /// Returns whether the session is older than the specified maximum age.
+    pub fn is_expired(&self, max_age: Duration) -> bool {
+        self.age() > max_age
+    }
+
+    /// Records that the peer was just heard from.
+    pub fn touch(&mut self) {
+        self.last_seen = unix_timestamp();
+    }
+
     pub fn uri(&self) -> Uri {
-        Uri::parse(&format!(
+        Uri::parse(format!(
             "wc:{}@1?{}",
             self.handshake_topic,
-            Serializer::new(String::new())
+            FormSerializer::new(String::new())
                 .append_pair("bridge", self.bridge.as_str())
                 .append_pair("key", self.key.display().as_str())
                 .finish()
@@ -51,15 +146,68 @@ impl Session {
         self.chain_id = Some(params.chain_id);
         self.peer_id = Some(params.peer_id);
         self.peer_meta = Some(params.peer_meta);
+
+        // NOTE: Applying session parameters means that a new session was
+        // approved by the peer, so the session's age starts from here.
+        self.created_at = unix_timestamp();
+        self.touch();
     }
 
     pub fn update(&mut self, update: SessionUpdate) {
         self.connected = update.approved;
         self.accounts = update.accounts;
         self.chain_id = Some(update.chain_id);
+        self.touch();
+    }
+}
+
+/// A snapshot of a session's connection state, accounts and chain.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SessionState {
+    pub connected: bool,
+    pub accounts: Vec<Address>,
+    pub chain_id: u64,
+}
+
+impl From<&Session> for SessionState {
+    fn from(session: &Session) -> Self {
+        SessionState {
+            connected: session.connected,
+            accounts: session.accounts.clone(),
+            chain_id: session.chain_id.unwrap_or_default(),
+        }
+    }
+}
+
+impl Serialize for Session {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut value = SessionSchema::serialize(self, serde_json::value::Serializer)
+            .map_err(ser::Error::custom)?;
+        if let Some(session) = value.as_object_mut() {
+            session.insert("version".into(), Session::VERSION.into());
+        }
+        value.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Session {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value =
+            Session::migrate(Value::deserialize(deserializer)?).map_err(de::Error::custom)?;
+        SessionSchema::deserialize(value).map_err(de::Error::custom)
     }
 }
 
+#[derive(Debug, Error)]
+pub enum SchemaError {
+    #[error("serialized session is not a JSON object")]
+    NotAnObject,
+    #[error("session schema version is not an integer")]
+    InvalidVersion,
+    #[error("unsupported session schema version {0}, this version of walletconnect-rs supports up to version {}", Session::VERSION)]
+    Unsupported(u64),
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -85,4 +233,49 @@ mod tests {
         let deserialized = serde_json::from_str(&serialized).unwrap();
         assert_eq!(topic, deserialized);
     }
+
+    fn legacy_session() -> Value {
+        json!({
+            "connected": true,
+            "accounts": ["0x000102030405060708090a0b0c0d0e0f10111213"],
+            "chainId": 1,
+            "bridge": "https://bridge.walletconnect.org/",
+            "key": "26075c07b19284e193101d7f27d7f96aa1802645663110a47c5c3bd3da580cae",
+            "clientId": "de5682be-2a03-4b8e-866e-1e89dbca422b",
+            "clientMeta": {
+                "description": "walletconnect-rs tests",
+                "url": "https://github.com/nlordell/walletconnect-rs",
+                "icons": [],
+                "name": "walletconnect-rs",
+            },
+            "peerId": "e8526892-8e47-42e4-9ea3-20c0b164bb83",
+            "peerMeta": null,
+            "handshakeId": 0,
+            "handshakeTopic": "8a5e5bdc-a0e4-4702-ba63-8f1a5655744f",
+        })
+    }
+
+    #[test]
+    fn migrates_unversioned_sessions() {
+        let session = serde_json::from_value::<Session>(legacy_session()).unwrap();
+        assert!(session.connected);
+        assert_eq!(session.chain_id, Some(1));
+        assert!(!session.is_expired(Duration::from_secs(60)));
+
+        let serialized = serde_json::to_value(&session).unwrap();
+        assert_eq!(serialized["version"], json!(Session::VERSION));
+        assert_eq!(
+            serde_json::from_value::<Session>(serialized)
+                .unwrap()
+                .client_id,
+            session.client_id,
+        );
+    }
+
+    #[test]
+    fn rejects_newer_session_versions() {
+        let mut session = legacy_session();
+        session["version"] = json!(Session::VERSION + 1);
+        assert!(serde_json::from_value::<Session>(session).is_err());
+    }
 }
@@ -2,33 +2,52 @@ use crate::crypto::{Key, OpenError, SealError};
 use crate::protocol::{SocketMessage, SocketMessageKind, Topic};
 use log::{trace, warn};
 use parity_ws::{Handler, Message, Sender, WebSocket};
+use std::collections::HashMap;
 use std::error::Error;
 use std::str::Utf8Error;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread::{self, JoinHandle};
 use thiserror::Error;
 use url::Url;
 
+/// A WebSocket connection to a WalletConnect bridge.
+///
+/// A single socket can be subscribed to multiple topics, each with their own
+/// symmetric key for opening the messages published to it.
+///
+/// Messages that can't be opened or handled are logged and dropped instead of
+/// closing the socket. This applies to sockets for a single client as well as
+/// sockets shared by a [`SessionManager`](super::SessionManager).
 #[derive(Debug)]
 pub struct Socket {
-    key: Key,
+    keys: Keys,
     sender: Sender,
     event_loop: JoinHandle<Result<(), parity_ws::Error>>,
 }
 
+#[derive(Clone, Debug, Default)]
+struct Keys(Arc<Mutex<HashMap<Topic, Key>>>);
+
+impl Keys {
+    fn lock(&self) -> MutexGuard<'_, HashMap<Topic, Key>> {
+        self.0.lock().expect("mutex guard should never be poisoned")
+    }
+}
+
 impl Socket {
     pub fn connect(
         url: Url,
-        key: Key,
         message_handler: impl MessageHandler + Send + 'static,
     ) -> Result<Self, SocketError> {
+        let keys = Keys::default();
         let mut socket = WebSocket::new({
-            let mut params = Some((key.clone(), message_handler));
+            let mut params = Some((keys.clone(), message_handler));
             move |sender| {
-                let (key, message_handler) = params
+                let (keys, message_handler) = params
                     .take()
                     .expect("more than one WebSocket connection established");
                 SocketHandler {
-                    key,
+                    keys,
                     sender,
                     message_handler,
                 }
@@ -46,34 +65,51 @@ impl Socket {
         });
 
         Ok(Socket {
-            key,
+            keys,
             sender,
             event_loop,
         })
     }
 
-    fn handle(&self) -> SocketHandle {
+    fn handle(&self) -> SocketHandle<'_> {
         SocketHandle {
-            key: &self.key,
             sender: &self.sender,
         }
     }
 
-    pub fn subscribe(&self, topic: Topic) -> Result<(), SocketError> {
+    /// Subscribes to messages published to a topic, using the specified key
+    /// to open them.
+    pub fn subscribe(&self, topic: Topic, key: Key) -> Result<(), SocketError> {
+        self.keys.lock().insert(topic.clone(), key);
         self.handle().subscribe(topic)
     }
 
+    /// Stops handling messages published to a topic.
+    ///
+    /// Note that the WalletConnect bridge protocol has no way to unsubscribe,
+    /// so messages for the topic are still received but ignored.
+    pub fn unsubscribe(&self, topic: &Topic) {
+        self.keys.lock().remove(topic);
+    }
+
     pub fn publish(
         &self,
         topic: Topic,
+        key: &Key,
         payload: impl AsRef<str>,
         silent: bool,
     ) -> Result<(), SocketError> {
-        self.handle().publish(topic, payload, silent)
+        self.handle().publish(topic, key, payload, silent)
     }
 
-    pub fn close(self) -> Result<(), SocketError> {
+    /// Shuts down the socket without waiting for its event loop to finish.
+    pub fn shutdown(&self) -> Result<(), SocketError> {
         self.sender.shutdown()?;
+        Ok(())
+    }
+
+    pub fn close(self) -> Result<(), SocketError> {
+        self.shutdown()?;
         self.event_loop
             .join()
             .expect("event loop should never panic")?;
@@ -94,7 +130,6 @@ pub enum SocketError {
 
 #[derive(Debug)]
 pub struct SocketHandle<'a> {
-    key: &'a Key,
     sender: &'a Sender,
 }
 
@@ -113,12 +148,13 @@ impl SocketHandle<'_> {
     pub fn publish(
         &self,
         topic: Topic,
+        key: &Key,
         payload: impl AsRef<str>,
         silent: bool,
     ) -> Result<(), SocketError> {
         trace!("sending payload '{}'", payload.as_ref());
 
-        let payload = self.key.seal(payload.as_ref())?;
+        let payload = key.seal(payload.as_ref())?;
         self.send(SocketMessage {
             topic,
             kind: SocketMessageKind::Pub,
@@ -151,7 +187,7 @@ pub trait MessageHandler {
 }
 
 struct SocketHandler<M> {
-    key: Key,
+    keys: Keys,
     sender: Sender,
     message_handler: M,
 }
@@ -174,7 +210,13 @@ where
             None => return Err(MessageError::MissingPayload),
         };
 
-        let opened = self.key.open(&payload)?;
+        let key = self
+            .keys
+            .lock()
+            .get(&topic)
+            .cloned()
+            .ok_or_else(|| MessageError::UnknownTopic(topic.clone()))?;
+        let opened = key.open(&payload)?;
         let decrypted = String::from_utf8(opened).map_err(|err| err.utf8_error())?;
 
         trace!("received payload '{}'", decrypted);
@@ -188,14 +230,23 @@ where
     M: MessageHandler,
 {
     fn on_message(&mut self, message: Message) -> parity_ws::Result<()> {
-        let (topic, payload) = self.decrypt_message(message.as_text()?).map_err(Box::new)?;
+        // NOTE: Messages that can't be opened are dropped instead of closing
+        // the socket, since the socket may be shared by many sessions and
+        // anyone can publish to a topic. This includes messages for topics
+        // that were unsubscribed from, which are expected.
+        let (topic, payload) = match self.decrypt_message(message.as_text()?) {
+            Ok(message) => message,
+            Err(err) => {
+                warn!("ignoring message: {}", err);
+                return Ok(());
+            }
+        };
         let handle = SocketHandle {
-            key: &self.key,
             sender: &self.sender,
         };
-        self.message_handler
-            .message(handle, topic, payload)
-            .map_err(Box::new)?;
+        if let Err(err) = self.message_handler.message(handle, topic.clone(), payload) {
+            warn!("error handling message for topic '{}': {}", topic, err);
+        }
 
         Ok(())
     }
@@ -209,6 +260,8 @@ pub enum MessageError {
     Sub(Topic),
     #[error("message payload missing")]
     MissingPayload,
+    #[error("received message for unknown topic '{0}'")]
+    UnknownTopic(Topic),
     #[error("failed to open AEAD payload: {0}")]
     Aead(#[from] OpenError),
     #[error("invalid UTF-8 in decrypted payload: {0}")]
@@ -1,76 +1,612 @@
 use super::options::Options;
 use super::session::Session;
-use log::warn;
-use serde::de::DeserializeOwned;
-use serde::Serialize;
+use crate::crypto::{self, Key, SealError};
+use crate::serialization;
+use data_encoding::DecodeError;
+use log::debug;
+use openssl::error::ErrorStack;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
 use std::env;
-use std::fs::File;
-use std::io;
+use std::error::Error;
+use std::fmt::{self, Debug, Formatter};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Write};
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tempfile::NamedTempFile;
+use thiserror::Error;
+use zeroize::Zeroizing;
 
-#[derive(Debug)]
-pub struct Storage<T> {
-    path: PathBuf,
-    value: T,
+/// A backend for persisting WalletConnect sessions.
+///
+/// Sessions are keyed by their profile name, so that a client using the same
+/// profile will resume a previously established session.
+pub trait SessionStore: Debug + Send + Sync {
+    /// Loads the session stored for the specified profile, returning `None`
+    /// if no session has been stored for it.
+    fn load(&self, profile: &Path) -> Result<Option<Session>, StorageError>;
+
+    /// Stores the session for the specified profile, replacing any existing
+    /// session.
+    fn save(&self, profile: &Path, session: &Session) -> Result<(), StorageError>;
+
+    /// Removes the session stored for the specified profile, if any.
+    fn delete(&self, profile: &Path) -> Result<(), StorageError>;
+
+    /// Lists all profiles with a stored session.
+    fn profiles(&self) -> Result<Vec<PathBuf>, StorageError>;
+
+    /// Acquires exclusive access to the specified profile for as long as the
+    /// returned lock is held, failing with [`StorageError::Locked`] if it is
+    /// already in use.
+    ///
+    /// By default, profiles are not locked.
+    fn lock(&self, profile: &Path) -> Result<ProfileLock, StorageError> {
+        let _ = profile;
+        Ok(ProfileLock::unlocked())
+    }
+}
+
+/// A guard granting exclusive access to a profile, released when dropped.
+pub struct ProfileLock(#[allow(dead_code)] Box<dyn Send + Sync>);
+
+impl ProfileLock {
+    pub fn new(guard: impl Send + Sync + 'static) -> Self {
+        ProfileLock(Box::new(guard))
+    }
+
+    pub fn unlocked() -> Self {
+        ProfileLock::new(())
+    }
+}
+
+impl Debug for ProfileLock {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("ProfileLock")
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("JSON serialization error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("unable to determine cache directory, neither $XDG_CACHE_HOME nor $HOME are set")]
+    NoCacheDir,
+    #[error("profile '{0}' is already in use")]
+    Locked(PathBuf),
+    #[error("profile '{0}' does not exist")]
+    NotFound(PathBuf),
+    #[error("profile '{0}' already exists")]
+    Exists(PathBuf),
+    #[error("profile '{0}' was created with different client metadata or connection options")]
+    Mismatch(PathBuf),
+    #[error("invalid session blob: {0}")]
+    Blob(#[from] DecodeError),
+    #[error("profile is encrypted but no profile key was provided")]
+    MissingKey,
+    #[error("profile was encrypted with a different kind of profile key")]
+    KeyKindMismatch,
+    #[error("failed to derive profile key: {0}")]
+    Kdf(#[from] ErrorStack),
+    #[error("failed to encrypt profile: {0}")]
+    Seal(#[from] SealError),
+    #[error(
+        "failed to decrypt profile, either the profile key is wrong or the profile was modified"
+    )]
+    Open,
+    #[error("session store error: {0}")]
+    Other(Box<dyn Error + Send + Sync>),
+}
+
+/// The secret used for encrypting session profiles at rest.
+#[derive(Clone)]
+pub struct ProfileKey(ProfileKeyKind);
+
+#[derive(Clone)]
+enum ProfileKeyKind {
+    Passphrase(Zeroizing<String>),
+    Raw(Key),
 }
 
-impl Storage<Session> {
-    pub fn for_session(options: Options) -> Self {
-        let path = session_profile_path(&options.profile);
-        let (value, save) = match Storage::load(&path) {
-            Ok(session) if options.matches(&session) => (session, false),
-            _ => (options.create_session(), true),
+impl ProfileKey {
+    /// Creates a profile key that derives the encryption key from a
+    /// passphrase with PBKDF2.
+    pub fn passphrase(passphrase: impl Into<String>) -> Self {
+        ProfileKey(ProfileKeyKind::Passphrase(Zeroizing::new(
+            passphrase.into(),
+        )))
+    }
+
+    /// Creates a profile key from raw 256-bit key material, skipping key
+    /// derivation.
+    pub fn raw(key: [u8; 32]) -> Self {
+        ProfileKey(ProfileKeyKind::Raw(Key::from_raw(key)))
+    }
+}
+
+impl Debug for ProfileKey {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("ProfileKey(********)")
+    }
+}
+
+const PBKDF2_ITERATIONS: u32 = 600_000;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "kdf", rename_all = "kebab-case")]
+enum Kdf {
+    None,
+    Pbkdf2Sha256 {
+        #[serde(with = "serialization::hexstring")]
+        salt: Vec<u8>,
+        iterations: u32,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EncryptedProfile {
+    encryption: Kdf,
+    #[serde(with = "serialization::hexstring")]
+    nonce: Vec<u8>,
+    #[serde(with = "serialization::hexstring")]
+    ciphertext: Vec<u8>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum ProfileDocument<'a> {
+    Plain(&'a Session),
+    Encrypted(EncryptedProfile),
+}
+
+impl<'a> ProfileDocument<'a> {
+    pub fn seal(
+        session: &'a Session,
+        encryption: Option<&Encryption>,
+    ) -> Result<Self, StorageError> {
+        Ok(match encryption {
+            Some(encryption) => ProfileDocument::Encrypted(encryption.seal(session)?),
+            None => ProfileDocument::Plain(session),
+        })
+    }
+
+    /// Opens a serialized profile document, returning the session along with
+    /// whether or not it was stored in plaintext.
+    pub fn open(
+        json: &[u8],
+        encryption: Option<&Encryption>,
+    ) -> Result<(Session, bool), StorageError> {
+        let value = serde_json::from_slice::<Value>(json)?;
+        if value.get("encryption").is_some() {
+            let encryption = encryption.ok_or(StorageError::MissingKey)?;
+            let session = encryption.open(serde_json::from_value(value)?)?;
+            Ok((session, false))
+        } else {
+            Ok((serde_json::from_value(value)?, true))
+        }
+    }
+}
+
+/// A profile key along with the key derivation parameters used for sealing
+/// new profiles, so that the (intentionally slow) key derivation only needs
+/// to happen once per store.
+#[derive(Clone, Debug)]
+pub struct Encryption {
+    secret: ProfileKey,
+    kdf: Kdf,
+    key: Key,
+}
+
+impl Encryption {
+    pub fn new(secret: ProfileKey) -> Result<Self, StorageError> {
+        let kdf = match &secret.0 {
+            ProfileKeyKind::Passphrase(_) => Kdf::Pbkdf2Sha256 {
+                salt: rand::random::<[u8; 16]>().to_vec(),
+                iterations: PBKDF2_ITERATIONS,
+            },
+            ProfileKeyKind::Raw(_) => Kdf::None,
         };
+        let key = Encryption::derive(&secret, &kdf)?;
+
+        Ok(Encryption { secret, kdf, key })
+    }
 
-        let resource = Storage { path, value };
-        if save {
-            resource.save();
+    fn derive(secret: &ProfileKey, kdf: &Kdf) -> Result<Key, StorageError> {
+        match (&secret.0, kdf) {
+            (ProfileKeyKind::Raw(key), Kdf::None) => Ok(key.clone()),
+            (ProfileKeyKind::Passphrase(passphrase), Kdf::Pbkdf2Sha256 { salt, iterations }) => Ok(
+                crypto::pbkdf2_sha256(passphrase.as_bytes(), salt, *iterations)?,
+            ),
+            _ => Err(StorageError::KeyKindMismatch),
         }
+    }
+
+    fn seal(&self, session: &Session) -> Result<EncryptedProfile, StorageError> {
+        let plaintext = Zeroizing::new(serde_json::to_vec(session)?);
+        // NOTE: The key derivation parameters are authenticated along with the
+        //   session, so that the profile header can't be modified without
+        //   being detected.
+        let aad = serde_json::to_vec(&self.kdf)?;
+        let (nonce, ciphertext) = crypto::aes256gcm_seal(&self.key, &aad, &plaintext)?;
 
-        resource
+        Ok(EncryptedProfile {
+            encryption: self.kdf.clone(),
+            nonce,
+            ciphertext,
+        })
+    }
+
+    fn open(&self, profile: EncryptedProfile) -> Result<Session, StorageError> {
+        let derived;
+        let key = match (&self.kdf, &profile.encryption) {
+            (
+                Kdf::Pbkdf2Sha256 { salt, iterations },
+                Kdf::Pbkdf2Sha256 {
+                    salt: profile_salt,
+                    iterations: profile_iterations,
+                },
+            ) if salt == profile_salt && iterations == profile_iterations => &self.key,
+            (_, kdf) => {
+                derived = Encryption::derive(&self.secret, kdf)?;
+                &derived
+            }
+        };
+        let aad = serde_json::to_vec(&profile.encryption)?;
+        let plaintext = crypto::aes256gcm_open(key, &profile.nonce, &aad, &profile.ciphertext)
+            .map_err(|_| StorageError::Open)?;
+        let plaintext = Zeroizing::new(plaintext);
+
+        Ok(serde_json::from_slice(&plaintext)?)
     }
 }
 
-impl<T: DeserializeOwned + Serialize> Storage<T> {
-    fn load(path: &Path) -> io::Result<T> {
-        let file = File::open(path)?;
-        let value = serde_json::from_reader(file)?;
+/// A session store that persists sessions as JSON files in a directory.
+///
+/// Profiles can optionally be encrypted at rest with a [`ProfileKey`], in
+/// which case existing plaintext profiles are transparently re-encrypted the
+/// first time they are loaded.
+///
+/// By default, profiles are stored in `$XDG_CACHE_HOME/walletconnect-rs/profiles`
+/// (falling back to `$HOME/.cache` when `$XDG_CACHE_HOME` is not set). Missing
+/// directories are created when profiles are first written.
+#[derive(Clone, Debug, Default)]
+pub struct FileStore {
+    dir: Option<PathBuf>,
+    encryption: Option<Encryption>,
+}
 
-        Ok(value)
+impl FileStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        FileStore {
+            dir: Some(dir.into()),
+            encryption: None,
+        }
+    }
+
+    pub fn encrypted(dir: impl Into<PathBuf>, key: ProfileKey) -> Result<Self, StorageError> {
+        Ok(FileStore {
+            dir: Some(dir.into()),
+            encryption: Some(Encryption::new(key)?),
+        })
     }
 
-    fn save(&self) {
-        if let Err(err) = self.try_save() {
-            warn!("error saving to '{}': {:?}", self.path.display(), err);
+    /// Returns the directory in which profiles are stored.
+    pub fn dir(&self) -> Result<PathBuf, StorageError> {
+        match &self.dir {
+            Some(dir) => Ok(dir.clone()),
+            None => {
+                let mut dir = default_wallectconnect_cache_dir().ok_or(StorageError::NoCacheDir)?;
+                dir.push("profiles");
+                Ok(dir)
+            }
         }
     }
 
-    fn try_save(&self) -> io::Result<()> {
-        let file = File::create(&self.path)?;
-        serde_json::to_writer_pretty(file, &self.value)?;
+    /// Returns the path of the file storing the specified profile's session.
+    pub fn profile_path(&self, profile: &Path) -> Result<PathBuf, StorageError> {
+        Ok(self.dir()?.join(profile).with_extension("json"))
+    }
+
+    fn lock_path(&self, profile: &Path) -> Result<PathBuf, StorageError> {
+        Ok(self.dir()?.join(profile).with_extension("lock"))
+    }
+
+    fn write_profile(&self, path: &Path, session: &Session) -> Result<(), StorageError> {
+        let document = ProfileDocument::seal(session, self.encryption.as_ref())?;
+        let json = Zeroizing::new(serde_json::to_vec_pretty(&document)?);
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        fs::create_dir_all(dir)?;
+
+        // NOTE: Write to a uniquely named temporary file (which is only
+        // readable by the current user) and atomically rename it over the
+        // profile, so that a crash mid-write never leaves a corrupt profile
+        // and concurrent writers never interleave their writes.
+        let mut file = NamedTempFile::new_in(dir)?;
+        file.write_all(&json)?;
+        file.as_file().sync_all()?;
+        file.persist(path).map_err(|err| err.error)?;
 
         Ok(())
     }
 
-    pub fn update<F>(&mut self, f: F)
+    /// Reads a profile, returning the session along with whether or not it
+    /// was stored in plaintext.
+    fn read(&self, profile: &Path) -> Result<Option<(Session, bool)>, StorageError> {
+        let json = match fs::read(self.profile_path(profile)?) {
+            Ok(json) => Zeroizing::new(json),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Some(ProfileDocument::open(
+            &json,
+            self.encryption.as_ref(),
+        )?))
+    }
+}
+
+impl SessionStore for FileStore {
+    fn load(&self, profile: &Path) -> Result<Option<Session>, StorageError> {
+        let session = match self.read(profile)? {
+            Some((session, plaintext)) if plaintext && self.encryption.is_some() => session,
+            session => return Ok(session.map(|(session, _)| session)),
+        };
+
+        // NOTE: Only re-encrypt plaintext profiles that are not in use, as
+        // clients re-save the profiles they use once they have locked them.
+        let _lock = match self.lock(profile) {
+            Ok(lock) => lock,
+            Err(StorageError::Locked(_)) => return Ok(Some(session)),
+            Err(err) => return Err(err),
+        };
+
+        // NOTE: Read the profile again now that it is locked, since it may
+        // have been written in the meantime.
+        match self.read(profile)? {
+            Some((session, true)) => {
+                debug!("encrypting plaintext profile '{}'", profile.display());
+                self.save(profile, &session)?;
+                Ok(Some(session))
+            }
+            session => Ok(session.map(|(session, _)| session)),
+        }
+    }
+
+    fn save(&self, profile: &Path, session: &Session) -> Result<(), StorageError> {
+        self.write_profile(&self.profile_path(profile)?, session)
+    }
+
+    /// Removes the profile along with its lock file, so callers must hold
+    /// the profile's lock.
+    fn delete(&self, profile: &Path) -> Result<(), StorageError> {
+        remove_file_if_exists(&self.profile_path(profile)?)?;
+        remove_file_if_exists(&self.lock_path(profile)?)
+    }
+
+    fn profiles(&self) -> Result<Vec<PathBuf>, StorageError> {
+        let entries = match fs::read_dir(self.dir()?) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut profiles = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                if let Some(profile) = path.file_stem() {
+                    profiles.push(PathBuf::from(profile));
+                }
+            }
+        }
+        profiles.sort();
+
+        Ok(profiles)
+    }
+
+    fn lock(&self, profile: &Path) -> Result<ProfileLock, StorageError> {
+        let path = self.lock_path(profile)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = create_private_file(&path)?;
+        match file.try_lock() {
+            Ok(()) => Ok(ProfileLock::new(file)),
+            Err(TryLockError::WouldBlock) => Err(StorageError::Locked(profile.to_owned())),
+            Err(TryLockError::Error(err)) => Err(err.into()),
+        }
+    }
+}
+
+/// Creates a file for writing that is only readable by the current user.
+fn create_private_file(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)
+}
+
+fn remove_file_if_exists(path: &Path) -> Result<(), StorageError> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// A session store that keeps sessions in memory, for environments where
+/// sessions should not outlive the process.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    sessions: Mutex<HashMap<PathBuf, Session>>,
+    locked: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn load(&self, profile: &Path) -> Result<Option<Session>, StorageError> {
+        let sessions = self
+            .sessions
+            .lock()
+            .expect("mutex guard should never be poisoned");
+        Ok(sessions.get(profile).cloned())
+    }
+
+    fn save(&self, profile: &Path, session: &Session) -> Result<(), StorageError> {
+        let mut sessions = self
+            .sessions
+            .lock()
+            .expect("mutex guard should never be poisoned");
+        sessions.insert(profile.to_owned(), session.clone());
+        Ok(())
+    }
+
+    fn delete(&self, profile: &Path) -> Result<(), StorageError> {
+        let mut sessions = self
+            .sessions
+            .lock()
+            .expect("mutex guard should never be poisoned");
+        sessions.remove(profile);
+        Ok(())
+    }
+
+    fn profiles(&self) -> Result<Vec<PathBuf>, StorageError> {
+        let sessions = self
+            .sessions
+            .lock()
+            .expect("mutex guard should never be poisoned");
+        let mut profiles = sessions.keys().cloned().collect::<Vec<_>>();
+        profiles.sort();
+        Ok(profiles)
+    }
+
+    fn lock(&self, profile: &Path) -> Result<ProfileLock, StorageError> {
+        struct Guard(Arc<Mutex<HashSet<PathBuf>>>, PathBuf);
+
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                if let Ok(mut locked) = self.0.lock() {
+                    locked.remove(&self.1);
+                }
+            }
+        }
+
+        let mut locked = self
+            .locked
+            .lock()
+            .expect("mutex guard should never be poisoned");
+        if !locked.insert(profile.to_owned()) {
+            return Err(StorageError::Locked(profile.to_owned()));
+        }
+
+        Ok(ProfileLock::new(Guard(
+            self.locked.clone(),
+            profile.to_owned(),
+        )))
+    }
+}
+
+#[derive(Debug)]
+pub struct Storage {
+    profile: PathBuf,
+    options: Options,
+    value: Session,
+    _lock: ProfileLock,
+}
+
+impl Storage {
+    pub fn for_session(options: Options) -> Result<Self, StorageError> {
+        let profile = options.profile.clone();
+        let store = options.store.clone();
+        let lock = store.lock(&profile)?;
+        // NOTE: Check that the stored session matches the options before
+        // checking whether it expired, so that profiles for other clients are
+        // never overwritten.
+        let value = match store.load(&profile)? {
+            Some(session) if !options.matches(&session) => {
+                return Err(StorageError::Mismatch(profile))
+            }
+            Some(session) if options.is_expired(&session) => {
+                debug!("discarding expired session '{}'", profile.display());
+                options.clone().create_session()
+            }
+            Some(session) if options.is_new_pairing(&session) => {
+                debug!("starting new session for '{}'", profile.display());
+                options.clone().create_session()
+            }
+            Some(session) => session,
+            None => options.clone().create_session(),
+        };
+
+        // NOTE: Resumed sessions are saved again, so that profiles that were
+        // migrated when they were loaded are persisted while the profile is
+        // locked.
+        let resource = Storage {
+            profile,
+            options,
+            value,
+            _lock: lock,
+        };
+        resource.save()?;
+
+        Ok(resource)
+    }
+
+    fn save(&self) -> Result<(), StorageError> {
+        self.options.store.save(&self.profile, &self.value)
+    }
+
+    /// Replaces the session with a newly created one, with a new key and
+    /// topics.
+    pub fn reset(&mut self) -> Result<(), StorageError> {
+        self.value = self.options.clone().create_session();
+        self.save()
+    }
+
+    /// Records that the peer was just heard from.
+    ///
+    /// This is only persisted with the next session update, so that profiles
+    /// aren't written for every message from the peer.
+    pub fn touch(&mut self) {
+        self.value.touch();
+    }
+
+    pub fn update<F>(&mut self, f: F) -> Result<(), StorageError>
     where
-        F: FnOnce(&mut T),
+        F: FnOnce(&mut Session),
     {
         f(&mut self.value);
-        self.save();
+        self.save()
     }
 }
 
-impl<T> Deref for Storage<T> {
-    type Target = T;
+impl Deref for Storage {
+    type Target = Session;
 
     fn deref(&self) -> &Self::Target {
         &self.value
     }
 }
 
-fn default_wallectconnect_cache_dir() -> PathBuf {
+fn default_wallectconnect_cache_dir() -> Option<PathBuf> {
     let mut cache = env::var_os("XDG_CACHE_HOME")
         .map(PathBuf::from)
         .or_else(|| {
@@ -79,16 +615,307 @@ fn default_wallectconnect_cache_dir() -> PathBuf {
                 home.push(".cache");
                 home
             })
-        })
-        .unwrap_or_else(|| PathBuf::from("/etc/cache"));
+        })?;
     cache.push("walletconnect-rs");
-    cache
+    Some(cache)
 }
 
-fn session_profile_path(profile: &Path) -> PathBuf {
-    let mut path = default_wallectconnect_cache_dir();
-    path.push("profiles");
-    path.push(profile);
-    path.set_extension("json");
-    path
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::options;
+    use crate::client::Connection;
+    use crate::uri::Uri;
+    use serde_json::json;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn resumes_stored_session() {
+        let store = Arc::new(MemoryStore::new());
+
+        let mut storage = Storage::for_session(options(store.clone())).unwrap();
+        storage.update(|session| session.connected = true).unwrap();
+        let client_id = storage.client_id.clone();
+        drop(storage);
+
+        let resumed = Storage::for_session(options(store)).unwrap();
+        assert!(resumed.connected);
+        assert_eq!(resumed.client_id, client_id);
+    }
+
+    #[test]
+    fn encrypted_profile_roundtrip() {
+        let dir = TempDir::new().unwrap();
+        let store = FileStore::encrypted(dir.path(), ProfileKey::passphrase("hunter2")).unwrap();
+        let session = options(Arc::new(MemoryStore::new())).create_session();
+
+        store.save(Path::new("test"), &session).unwrap();
+        let contents = std::fs::read_to_string(dir.path().join("test.json")).unwrap();
+        assert!(!contents.contains(session.key.display().as_str()));
+
+        let reopened = FileStore::encrypted(dir.path(), ProfileKey::passphrase("hunter2")).unwrap();
+        let loaded = reopened.load(Path::new("test")).unwrap().unwrap();
+        assert_eq!(loaded.key, session.key);
+
+        let wrong = FileStore::encrypted(dir.path(), ProfileKey::passphrase("hunter3")).unwrap();
+        assert!(matches!(
+            wrong.load(Path::new("test")),
+            Err(StorageError::Open)
+        ));
+        assert!(matches!(
+            FileStore::new(dir.path()).load(Path::new("test")),
+            Err(StorageError::MissingKey)
+        ));
+    }
+
+    #[test]
+    fn detects_tampered_profiles() {
+        let dir = TempDir::new().unwrap();
+        let store = FileStore::encrypted(dir.path(), ProfileKey::passphrase("hunter2")).unwrap();
+        let session = options(Arc::new(MemoryStore::new())).create_session();
+        store.save(Path::new("test"), &session).unwrap();
+
+        let path = dir.path().join("test.json");
+        let contents = std::fs::read(&path).unwrap();
+        let tamper = |f: fn(&mut Value)| {
+            let mut document = serde_json::from_slice::<Value>(&contents).unwrap();
+            f(&mut document);
+            std::fs::write(&path, serde_json::to_vec(&document).unwrap()).unwrap();
+            store.load(Path::new("test"))
+        };
+
+        assert!(matches!(
+            tamper(|document| document["encryption"]["salt"] = json!("00".repeat(16))),
+            Err(StorageError::Open)
+        ));
+        assert!(matches!(
+            tamper(|document| {
+                let ciphertext = document["ciphertext"].as_str().unwrap();
+                let flipped = if ciphertext.starts_with('0') {
+                    "1"
+                } else {
+                    "0"
+                };
+                document["ciphertext"] = json!(format!("{}{}", flipped, &ciphertext[1..]));
+            }),
+            Err(StorageError::Open)
+        ));
+        assert!(tamper(|_| ()).unwrap().is_some());
+    }
+
+    #[test]
+    fn migrates_plaintext_profile() {
+        let dir = TempDir::new().unwrap();
+        let session = options(Arc::new(MemoryStore::new())).create_session();
+        FileStore::new(dir.path())
+            .save(Path::new("test"), &session)
+            .unwrap();
+
+        let store = FileStore::encrypted(dir.path(), ProfileKey::raw([42; 32])).unwrap();
+        let loaded = store.load(Path::new("test")).unwrap().unwrap();
+        assert_eq!(loaded.key, session.key);
+
+        let contents = std::fs::read_to_string(dir.path().join("test.json")).unwrap();
+        assert!(contents.contains("encryption"));
+        assert!(!contents.contains(session.key.display().as_str()));
+    }
+
+    #[test]
+    fn migrates_plaintext_profile_only_when_unlocked() {
+        let dir = TempDir::new().unwrap();
+        let session = options(Arc::new(MemoryStore::new())).create_session();
+        FileStore::new(dir.path())
+            .save(Path::new("test"), &session)
+            .unwrap();
+
+        let store = Arc::new(FileStore::encrypted(dir.path(), ProfileKey::raw([42; 32])).unwrap());
+        let lock = store.lock(Path::new("test")).unwrap();
+        store.load(Path::new("test")).unwrap().unwrap();
+        let contents = std::fs::read_to_string(dir.path().join("test.json")).unwrap();
+        assert!(!contents.contains("encryption"));
+        drop(lock);
+
+        let storage = Storage::for_session(options(store)).unwrap();
+        let contents = std::fs::read_to_string(dir.path().join("test.json")).unwrap();
+        assert!(contents.contains("encryption"));
+        drop(storage);
+    }
+
+    #[test]
+    fn concurrent_profile_writes() {
+        let dir = TempDir::new().unwrap();
+        let store = Arc::new(FileStore::new(dir.path()));
+        let session = options(Arc::new(MemoryStore::new())).create_session();
+
+        let writers = (0..8)
+            .map(|_| {
+                let (store, session) = (store.clone(), session.clone());
+                std::thread::spawn(move || {
+                    for _ in 0..10 {
+                        store.save(Path::new("test"), &session).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        assert_eq!(store.profiles().unwrap(), [PathBuf::from("test")]);
+        assert!(store.load(Path::new("test")).unwrap().is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn profiles_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new().unwrap();
+        let session = options(Arc::new(MemoryStore::new())).create_session();
+        FileStore::new(dir.path())
+            .save(Path::new("test"), &session)
+            .unwrap();
+
+        let metadata = std::fs::metadata(dir.path().join("test.json")).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn profiles_are_exclusive() {
+        let dir = TempDir::new().unwrap();
+        let store = Arc::new(FileStore::new(dir.path()));
+
+        let storage = Storage::for_session(options(store.clone())).unwrap();
+        assert!(matches!(
+            Storage::for_session(options(store.clone())),
+            Err(StorageError::Locked(_))
+        ));
+
+        drop(storage);
+        assert!(Storage::for_session(options(store)).is_ok());
+    }
+
+    #[test]
+    fn creates_missing_profile_directories() {
+        let dir = TempDir::new().unwrap();
+        let store = Arc::new(FileStore::new(dir.path().join("nested").join("profiles")));
+
+        let storage = Storage::for_session(options(store.clone())).unwrap();
+        assert!(store.profile_path(Path::new("test")).unwrap().is_file());
+        drop(storage);
+    }
+
+    #[test]
+    fn reports_persistence_errors() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("test.json"), "not a session").unwrap();
+
+        let store = Arc::new(FileStore::new(dir.path()));
+        assert!(matches!(
+            Storage::for_session(options(store)),
+            Err(StorageError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn reports_mismatched_sessions() {
+        let store = Arc::new(MemoryStore::new());
+        let mut stored = options(store.clone()).create_session();
+        stored.client_meta.name = "other".into();
+        store.save(Path::new("test"), &stored).unwrap();
+
+        assert!(matches!(
+            Storage::for_session(options(store.clone())),
+            Err(StorageError::Mismatch(_))
+        ));
+        assert_eq!(
+            store
+                .load(Path::new("test"))
+                .unwrap()
+                .unwrap()
+                .client_meta
+                .name,
+            "other",
+        );
+    }
+
+    #[test]
+    fn repairs_profiles_with_new_uris() {
+        let store = Arc::new(MemoryStore::new());
+        let with_uri = |uri: &Uri| Options {
+            connection: Connection::Uri(uri.clone()),
+            ..options(store.clone())
+        };
+
+        let uri = options(store.clone()).create_session().uri();
+        let mut storage = Storage::for_session(with_uri(&uri)).unwrap();
+        storage.update(|session| session.connected = true).unwrap();
+        drop(storage);
+
+        let resumed = Storage::for_session(with_uri(&uri)).unwrap();
+        assert!(resumed.connected);
+        drop(resumed);
+
+        let new_uri = options(store.clone()).create_session().uri();
+        let repaired = Storage::for_session(with_uri(&new_uri)).unwrap();
+        assert!(!repaired.connected);
+        assert_eq!(repaired.uri(), new_uri);
+    }
+
+    #[test]
+    fn keeps_expired_mismatched_sessions() {
+        let store = Arc::new(MemoryStore::new());
+        let mut stored = options(store.clone()).create_session();
+        stored.client_meta.name = "other".into();
+        stored.created_at -= 3600;
+        store.save(Path::new("test"), &stored).unwrap();
+
+        assert!(matches!(
+            Storage::for_session(Options {
+                max_age: Some(Duration::from_secs(60)),
+                ..options(store.clone())
+            }),
+            Err(StorageError::Mismatch(_))
+        ));
+        assert_eq!(
+            store.load(Path::new("test")).unwrap().unwrap().client_id,
+            stored.client_id,
+        );
+    }
+
+    #[test]
+    fn persists_migrated_profiles() {
+        let dir = TempDir::new().unwrap();
+        let store = Arc::new(FileStore::new(dir.path()));
+        let path = dir.path().join("test.json");
+
+        let mut legacy = serde_json::to_value(options(store.clone()).create_session()).unwrap();
+        for field in ["version", "createdAt", "lastSeen"] {
+            legacy.as_object_mut().unwrap().remove(field);
+        }
+        std::fs::write(&path, legacy.to_string()).unwrap();
+
+        let created_at = Storage::for_session(options(store.clone()))
+            .unwrap()
+            .created_at;
+        let persisted = serde_json::from_slice::<Value>(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(persisted["version"], Value::from(Session::VERSION));
+        assert_eq!(persisted["createdAt"], Value::from(created_at));
+    }
+
+    #[test]
+    fn discards_expired_sessions() {
+        let store = Arc::new(MemoryStore::new());
+        let mut stored = options(store.clone()).create_session();
+        stored.created_at -= 3600;
+        store.save(Path::new("test"), &stored).unwrap();
+
+        let storage = Storage::for_session(Options {
+            max_age: Some(Duration::from_secs(60)),
+            ..options(store)
+        })
+        .unwrap();
+        assert_ne!(storage.client_id, stored.client_id);
+    }
 }
@@ -1,5 +1,9 @@
 mod aead;
+mod gcm;
+mod kdf;
 mod key;
 
 pub use aead::{OpenError, SealError};
-pub use key::{DisplayKey, Key};
+pub use gcm::{aes256gcm_open, aes256gcm_seal};
+pub use kdf::pbkdf2_sha256;
+pub use key::Key;
@@ -48,6 +48,8 @@ pub fn open(key: &[u8], payload: &EncryptionPayload) -> Result<Vec<u8>, OpenErro
 pub enum SealError {
     #[error("internal OpenSSL error: {0}")]
     OpenSsl(#[from] ErrorStack),
+    #[error("internal cryptography error")]
+    Ring,
 }
 
 #[derive(Debug, Error)]
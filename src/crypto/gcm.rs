@@ -0,0 +1,65 @@
+use super::aead::{OpenError, SealError};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+
+fn key(key: &[u8]) -> Result<LessSafeKey, ring::error::Unspecified> {
+    Ok(LessSafeKey::new(UnboundKey::new(&AES_256_GCM, key)?))
+}
+
+/// Encrypts and authenticates the plaintext along with the associated data
+/// with AES-256-GCM, returning a random nonce and the ciphertext (with the
+/// authentication tag appended).
+pub fn aes256gcm_seal(
+    key_bytes: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>), SealError> {
+    let nonce = rand::random::<[u8; NONCE_LEN]>();
+    let mut ciphertext = plaintext.to_vec();
+    key(key_bytes)
+        .and_then(|key| {
+            key.seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(aad),
+                &mut ciphertext,
+            )
+        })
+        .map_err(|_| SealError::Ring)?;
+
+    Ok((nonce.to_vec(), ciphertext))
+}
+
+pub fn aes256gcm_open(
+    key_bytes: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, OpenError> {
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| OpenError::Verify)?;
+    let mut plaintext = ciphertext.to_vec();
+    let len = key(key_bytes)
+        .and_then(|key| key.open_in_place(nonce, Aad::from(aad), &mut plaintext))
+        .map_err(|_| OpenError::Verify)?
+        .len();
+    plaintext.truncate(len);
+
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::Key;
+
+    #[test]
+    fn roundtrip() {
+        let key = Key::random();
+        let (nonce, ciphertext) = aes256gcm_seal(&key, b"header", b"walletconnect-rs").unwrap();
+
+        let plaintext = aes256gcm_open(&key, &nonce, b"header", &ciphertext).unwrap();
+        assert_eq!(plaintext, b"walletconnect-rs");
+        assert!(matches!(
+            aes256gcm_open(&key, &nonce, b"tampered", &ciphertext),
+            Err(OpenError::Verify)
+        ));
+    }
+}
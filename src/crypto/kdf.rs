@@ -0,0 +1,34 @@
+use super::Key;
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::pkcs5;
+use zeroize::Zeroizing;
+
+pub fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32) -> Result<Key, ErrorStack> {
+    let mut raw = Zeroizing::new([0; 32]);
+    pkcs5::pbkdf2_hmac(
+        password,
+        salt,
+        iterations as _,
+        MessageDigest::sha256(),
+        &mut raw[..],
+    )?;
+
+    Ok(Key::from_raw(*raw))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    #[test]
+    fn pbkdf2_sha256_test_vector() {
+        // Test vector from RFC 7914, section 11.
+        let key = pbkdf2_sha256(b"passwd", b"salt", 1).unwrap();
+        assert_eq!(
+            hex::encode(&*key),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc",
+        );
+    }
+}
@@ -9,7 +9,6 @@ use std::fmt::{self, Debug, Display, Formatter};
 use std::hash::{Hash, Hasher};
 use std::ops::Deref;
 use std::str::FromStr;
-use thiserror::Error;
 use zeroize::Zeroizing;
 
 #[derive(Clone, Eq, PartialEq)]
@@ -53,7 +52,7 @@ impl Debug for Key {
     }
 }
 
-#[allow(clippy::derive_hash_xor_eq)]
+#[allow(clippy::derived_hash_with_manual_eq)]
 impl Hash for Key {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.0.hash(state);
@@ -68,10 +67,6 @@ impl Deref for Key {
     }
 }
 
-#[derive(Clone, Copy, Debug, Default, Error, Eq, PartialEq)]
-#[error("key must be exactly 32 bytes")]
-pub struct KeyLengthError;
-
 #[derive(Debug)]
 pub struct DisplayKey(String);
 
@@ -1 +1,149 @@
+use jsonrpc_core::Error;
 
+/// A classification of the JSON RPC error codes returned by wallets, as
+/// specified by EIP-1193 and EIP-1474.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RpcErrorKind {
+    /// The user rejected the request (4001).
+    UserRejected,
+    /// The requested method and/or account has not been authorized by the
+    /// user (4100).
+    Unauthorized,
+    /// The wallet does not support the requested method (4200).
+    UnsupportedMethod,
+    /// The wallet is disconnected from all chains (4900).
+    Disconnected,
+    /// The wallet is not connected to the requested chain (4901).
+    ChainDisconnected,
+    /// Missing or invalid parameters (-32000).
+    InvalidInput,
+    /// The requested resource was not found (-32001).
+    ResourceNotFound,
+    /// The requested resource is not available (-32002).
+    ResourceUnavailable,
+    /// The transaction creation failed (-32003).
+    TransactionRejected,
+    /// The method is not implemented (-32004).
+    MethodNotSupported,
+    /// The request exceeds a defined limit (-32005).
+    LimitExceeded,
+    /// Invalid JSON was received (-32700).
+    ParseError,
+    /// The JSON sent is not a valid request object (-32600).
+    InvalidRequest,
+    /// The method does not exist or is not available (-32601).
+    MethodNotFound,
+    /// Invalid method parameters (-32602).
+    InvalidParams,
+    /// Internal JSON RPC error (-32603).
+    InternalError,
+    /// Any other error code.
+    Other(i64),
+}
+
+impl RpcErrorKind {
+    pub fn from_code(code: i64) -> Self {
+        match code {
+            4001 => RpcErrorKind::UserRejected,
+            4100 => RpcErrorKind::Unauthorized,
+            4200 => RpcErrorKind::UnsupportedMethod,
+            4900 => RpcErrorKind::Disconnected,
+            4901 => RpcErrorKind::ChainDisconnected,
+            -32000 => RpcErrorKind::InvalidInput,
+            -32001 => RpcErrorKind::ResourceNotFound,
+            -32002 => RpcErrorKind::ResourceUnavailable,
+            -32003 => RpcErrorKind::TransactionRejected,
+            -32004 => RpcErrorKind::MethodNotSupported,
+            -32005 => RpcErrorKind::LimitExceeded,
+            -32700 => RpcErrorKind::ParseError,
+            -32600 => RpcErrorKind::InvalidRequest,
+            -32601 => RpcErrorKind::MethodNotFound,
+            -32602 => RpcErrorKind::InvalidParams,
+            -32603 => RpcErrorKind::InternalError,
+            code => RpcErrorKind::Other(code),
+        }
+    }
+
+    pub fn of(err: &Error) -> Self {
+        RpcErrorKind::from_code(err.code.code())
+    }
+
+    pub fn code(&self) -> i64 {
+        match self {
+            RpcErrorKind::UserRejected => 4001,
+            RpcErrorKind::Unauthorized => 4100,
+            RpcErrorKind::UnsupportedMethod => 4200,
+            RpcErrorKind::Disconnected => 4900,
+            RpcErrorKind::ChainDisconnected => 4901,
+            RpcErrorKind::InvalidInput => -32000,
+            RpcErrorKind::ResourceNotFound => -32001,
+            RpcErrorKind::ResourceUnavailable => -32002,
+            RpcErrorKind::TransactionRejected => -32003,
+            RpcErrorKind::MethodNotSupported => -32004,
+            RpcErrorKind::LimitExceeded => -32005,
+            RpcErrorKind::ParseError => -32700,
+            RpcErrorKind::InvalidRequest => -32600,
+            RpcErrorKind::MethodNotFound => -32601,
+            RpcErrorKind::InvalidParams => -32602,
+            RpcErrorKind::InternalError => -32603,
+            RpcErrorKind::Other(code) => *code,
+        }
+    }
+
+    /// Returns true if the error was caused by the user declining the request
+    /// in their wallet.
+    pub fn is_user_rejection(&self) -> bool {
+        matches!(self, RpcErrorKind::UserRejected)
+    }
+
+    /// Returns true if the error indicates that the wallet is not connected,
+    /// either to any chain or to the requested chain.
+    pub fn is_disconnected(&self) -> bool {
+        matches!(
+            self,
+            RpcErrorKind::Disconnected | RpcErrorKind::ChainDisconnected
+        )
+    }
+
+    /// Returns true if the wallet does not support or implement the
+    /// requested method.
+    pub fn is_unsupported(&self) -> bool {
+        matches!(
+            self,
+            RpcErrorKind::UnsupportedMethod
+                | RpcErrorKind::MethodNotSupported
+                | RpcErrorKind::MethodNotFound
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonrpc_core::ErrorCode;
+
+    #[test]
+    fn error_code_roundtrip() {
+        for code in [4001, 4100, 4200, 4900, 4901, -32003, -32601, -32099, 1] {
+            assert_eq!(RpcErrorKind::from_code(code).code(), code);
+        }
+    }
+
+    #[test]
+    fn classifies_rpc_errors() {
+        let rejected = Error {
+            code: ErrorCode::ServerError(4001),
+            message: "User rejected the request.".into(),
+            data: None,
+        };
+        assert!(RpcErrorKind::of(&rejected).is_user_rejection());
+        assert_eq!(
+            RpcErrorKind::of(&Error::method_not_found()),
+            RpcErrorKind::MethodNotFound
+        );
+        assert_eq!(
+            RpcErrorKind::of(&Error::new(ErrorCode::ServerError(-32000))),
+            RpcErrorKind::InvalidInput
+        );
+    }
+}
@@ -0,0 +1,439 @@
+//! Integration with `ethers-rs` signers and middleware.
+
+use crate::client::{CallError, Client, NotConnectedError};
+use crate::hex;
+use crate::protocol::{Transaction, TransactionConversionError};
+use crate::verify::{SignedTransaction, SignedTransactionError};
+use async_trait::async_trait;
+use ethers_core::types::transaction::eip2718::TypedTransaction;
+use ethers_core::types::transaction::eip712::{Eip712, TypedData};
+use ethers_core::types::{Address, BlockId, Bytes, NameOrAddress, Signature, SignatureError};
+use ethers_providers::{Middleware, MiddlewareError, PendingTransaction};
+use ethers_signers::Signer;
+use std::convert::TryFrom;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// An `ethers` signer that requests signatures from the wallet connected to
+/// a WalletConnect session.
+///
+/// Transactions must have all of their fields set, including the nonce, gas
+/// limit and fees, as the signature is otherwise for a transaction filled in
+/// by the wallet.
+///
+/// Typed data is not supported by [`Signer::sign_typed_data`], which always
+/// fails with [`SignerError::UnsupportedTypedData`] since `Eip712` payloads
+/// only expose their hashes and wallets need the full typed data. Typed data
+/// must be signed with [`WalletSigner::sign_typed_data_json`] instead.
+#[derive(Clone, Debug)]
+pub struct WalletSigner {
+    client: Arc<Client>,
+    address: Address,
+    chain_id: u64,
+}
+
+impl WalletSigner {
+    /// Creates a new signer for the session's first account and current
+    /// chain.
+    pub fn new(client: Client) -> Result<Self, NotConnectedError> {
+        WalletSigner::with_shared(Arc::new(client))
+    }
+
+    /// Creates a new signer from a client that is shared with other parts of
+    /// the application.
+    pub fn with_shared(client: Arc<Client>) -> Result<Self, NotConnectedError> {
+        let (accounts, chain_id) = client.accounts()?;
+        let address = *accounts.first().ok_or(NotConnectedError)?;
+        Ok(WalletSigner {
+            client,
+            address,
+            chain_id,
+        })
+    }
+
+    /// Sets the session account to sign with.
+    pub fn with_address(mut self, address: Address) -> Self {
+        self.address = address;
+        self
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Requests an EIP-712 signature for the specified typed data with
+    /// `eth_signTypedData_v4`.
+    pub async fn sign_typed_data_json(
+        &self,
+        typed_data: &TypedData,
+    ) -> Result<Signature, SignerError> {
+        let typed_data = serde_json::to_value(typed_data).map_err(SignerError::TypedData)?;
+        let signature = self
+            .client
+            .sign_typed_data(self.address, &typed_data)
+            .await?;
+        Ok(Signature::try_from(signature.as_ref())?)
+    }
+
+    async fn personal_sign(
+        &self,
+        address: Address,
+        message: &[u8],
+    ) -> Result<Signature, SignerError> {
+        let data = format!("0x{}", hex::encode(message));
+        let address = format!("{:?}", address);
+        self.client
+            .personal_sign(&[&data, &address])
+            .await
+            .map_err(|err| match err {
+                CallError::Signature(err) => SignerError::Signature(err),
+                err => SignerError::Call(err),
+            })
+    }
+
+    async fn sign_typed_transaction(
+        &self,
+        transaction: &TypedTransaction,
+    ) -> Result<Signature, SignerError> {
+        let requested = with_defaults(transaction.clone(), self.address, self.chain_id);
+        let transaction = Transaction::try_from(requested.clone())?;
+        let raw = self.client.sign_transaction(transaction.clone()).await?;
+        let signed = SignedTransaction::verified(raw, &transaction)?;
+        requested_signature(&requested, &signed)
+    }
+}
+
+/// Defaults the sender and chain ID of a transaction.
+fn with_defaults(
+    mut transaction: TypedTransaction,
+    from: Address,
+    chain_id: u64,
+) -> TypedTransaction {
+    if transaction.from().is_none() {
+        transaction.set_from(from);
+    }
+    if transaction.chain_id().is_none() {
+        transaction.set_chain_id(chain_id);
+    }
+    transaction
+}
+
+/// Converts a transaction into a wallet transaction request, defaulting to the
+/// specified sender and chain ID.
+fn wallet_transaction(
+    transaction: TypedTransaction,
+    from: Address,
+    chain_id: u64,
+) -> Result<Transaction, TransactionConversionError> {
+    Transaction::try_from(with_defaults(transaction, from, chain_id))
+}
+
+/// Returns the signature of a signed transaction, as long as the wallet signed
+/// exactly the requested transaction.
+///
+/// Wallets fill in fields that are missing from the request, such as the
+/// nonce and fees, in which case the signature is not valid for the requested
+/// transaction.
+fn requested_signature(
+    requested: &TypedTransaction,
+    signed: &SignedTransaction,
+) -> Result<Signature, SignerError> {
+    if signed.transaction.sighash() != requested.sighash() {
+        return Err(SignerError::Incomplete);
+    }
+    Ok(signed.signature)
+}
+
+#[async_trait]
+impl Signer for WalletSigner {
+    type Error = SignerError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<Signature, Self::Error> {
+        self.personal_sign(self.address, message.as_ref()).await
+    }
+
+    async fn sign_transaction(&self, message: &TypedTransaction) -> Result<Signature, Self::Error> {
+        self.sign_typed_transaction(message).await
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(
+        &self,
+        _: &T,
+    ) -> Result<Signature, Self::Error> {
+        // NOTE: Wallets need the full typed data and not just its hash, which
+        // can't be recovered from arbitrary `Eip712` payloads.
+        Err(SignerError::UnsupportedTypedData)
+    }
+
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    fn with_chain_id<T: Into<u64>>(mut self, chain_id: T) -> Self {
+        self.chain_id = chain_id.into();
+        self
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SignerError {
+    #[error(transparent)]
+    Call(#[from] CallError),
+    #[error("invalid transaction: {0}")]
+    Transaction(#[from] TransactionConversionError),
+    #[error("invalid signed transaction: {0}")]
+    SignedTransaction(#[from] SignedTransactionError),
+    #[error("invalid signature: {0}")]
+    Signature(#[from] SignatureError),
+    #[error("failed to encode typed data: {0}")]
+    TypedData(serde_json::Error),
+    #[error("typed data must be signed with `WalletSigner::sign_typed_data_json`")]
+    UnsupportedTypedData,
+    #[error("wallet filled in missing fields of the transaction, all fields must be set for the signature to be valid")]
+    Incomplete,
+}
+
+/// An `ethers` middleware that sends transactions and signing requests to the
+/// wallet connected to a WalletConnect session, and delegates all other
+/// requests to an inner middleware.
+#[derive(Clone, Debug)]
+pub struct WalletMiddleware<M> {
+    inner: M,
+    signer: WalletSigner,
+}
+
+impl<M> WalletMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Creates a new middleware for the session's first account.
+    pub fn new(inner: M, client: Client) -> Result<Self, NotConnectedError> {
+        Ok(WalletMiddleware::with_signer(
+            inner,
+            WalletSigner::new(client)?,
+        ))
+    }
+
+    pub fn with_signer(inner: M, signer: WalletSigner) -> Self {
+        WalletMiddleware { inner, signer }
+    }
+
+    pub fn signer(&self) -> &WalletSigner {
+        &self.signer
+    }
+
+    /// Resolves an ENS recipient with the inner middleware, since wallets
+    /// only accept transactions to addresses.
+    async fn resolve_recipient(
+        &self,
+        transaction: &mut TypedTransaction,
+    ) -> Result<(), WalletMiddlewareError<M>> {
+        if let Some(NameOrAddress::Name(name)) = transaction.to() {
+            let address = self
+                .inner
+                .resolve_name(name)
+                .await
+                .map_err(WalletMiddlewareError::Middleware)?;
+            transaction.set_to(address);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<M> Middleware for WalletMiddleware<M>
+where
+    M: Middleware,
+{
+    type Error = WalletMiddlewareError<M>;
+    type Provider = M::Provider;
+    type Inner = M;
+
+    fn inner(&self) -> &M {
+        &self.inner
+    }
+
+    fn default_sender(&self) -> Option<Address> {
+        Some(self.signer.address)
+    }
+
+    async fn is_signer(&self) -> bool {
+        true
+    }
+
+    async fn get_accounts(&self) -> Result<Vec<Address>, Self::Error> {
+        let (accounts, _) = self.signer.client.accounts().map_err(CallError::from)?;
+        Ok(accounts)
+    }
+
+    async fn send_transaction<T: Into<TypedTransaction> + Send + Sync>(
+        &self,
+        tx: T,
+        _: Option<BlockId>,
+    ) -> Result<PendingTransaction<'_, Self::Provider>, Self::Error> {
+        let mut tx = tx.into();
+        self.resolve_recipient(&mut tx).await?;
+
+        // NOTE: The wallet fills in the nonce and fees and then signs and
+        // broadcasts the transaction, so only the hash is returned.
+        let transaction = wallet_transaction(tx, self.signer.address, self.signer.chain_id)
+            .map_err(SignerError::from)?;
+        let hash = self
+            .signer
+            .client
+            .send_transaction(transaction)
+            .await
+            .map_err(SignerError::from)?;
+        Ok(PendingTransaction::new(hash, self.provider()))
+    }
+
+    async fn sign<T: Into<Bytes> + Send + Sync>(
+        &self,
+        data: T,
+        from: &Address,
+    ) -> Result<Signature, Self::Error> {
+        Ok(self.signer.personal_sign(*from, &data.into()).await?)
+    }
+
+    async fn sign_transaction(
+        &self,
+        tx: &TypedTransaction,
+        from: Address,
+    ) -> Result<Signature, Self::Error> {
+        let mut tx = tx.clone();
+        tx.set_from(from);
+        self.resolve_recipient(&mut tx).await?;
+        Ok(self.signer.sign_typed_transaction(&tx).await?)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum WalletMiddlewareError<M: Middleware> {
+    #[error(transparent)]
+    Signer(#[from] SignerError),
+    #[error("{0}")]
+    Middleware(M::Error),
+}
+
+impl<M> From<CallError> for WalletMiddlewareError<M>
+where
+    M: Middleware,
+{
+    fn from(err: CallError) -> Self {
+        WalletMiddlewareError::Signer(err.into())
+    }
+}
+
+impl<M> MiddlewareError for WalletMiddlewareError<M>
+where
+    M: Middleware,
+{
+    type Inner = M::Error;
+
+    fn from_err(err: M::Error) -> Self {
+        WalletMiddlewareError::Middleware(err)
+    }
+
+    fn as_inner(&self) -> Option<&Self::Inner> {
+        match self {
+            WalletMiddlewareError::Middleware(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{connect, Bridge};
+    use ethers_core::types::transaction::eip1559::Eip1559TransactionRequest;
+    use ethers_core::types::{TransactionRequest, H256};
+    use ethers_providers::Provider;
+    use ethers_signers::LocalWallet;
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    #[test]
+    fn wallet_transaction_defaults() {
+        let request: TypedTransaction = TransactionRequest::new()
+            .to(Address::repeat_byte(2))
+            .value(1_000)
+            .into();
+
+        let transaction =
+            wallet_transaction(request.clone(), Address::repeat_byte(1), 100).unwrap();
+        assert_eq!(transaction.from, Address::repeat_byte(1));
+        assert_eq!(transaction.chain_id, Some(100.into()));
+
+        let mut request = request;
+        request.set_from(Address::repeat_byte(3)).set_chain_id(1);
+        let transaction = wallet_transaction(request, Address::repeat_byte(1), 100).unwrap();
+        assert_eq!(transaction.from, Address::repeat_byte(3));
+        assert_eq!(transaction.chain_id, Some(1.into()));
+    }
+
+    #[test]
+    fn unresolved_recipients() {
+        let request: TypedTransaction = TransactionRequest::new().to("vitalik.eth").into();
+        assert!(matches!(
+            wallet_transaction(request, Address::zero(), 1),
+            Err(TransactionConversionError::UnresolvedName(name)) if name == "vitalik.eth"
+        ));
+    }
+
+    #[test]
+    fn rejects_signatures_for_filled_transactions() {
+        let wallet = LocalWallet::from_bytes(&[42; 32]).unwrap();
+        let requested: TypedTransaction = Eip1559TransactionRequest::new()
+            .from(wallet.address())
+            .to(Address::repeat_byte(2))
+            .value(1_000)
+            .gas(21_000)
+            .max_fee_per_gas(100)
+            .max_priority_fee_per_gas(2)
+            .chain_id(1)
+            .into();
+        let sign = |transaction: &TypedTransaction| {
+            let signature = wallet.sign_transaction_sync(transaction).unwrap();
+            SignedTransaction::decode(transaction.rlp_signed(&signature)).unwrap()
+        };
+
+        let mut filled = requested.clone();
+        filled.set_nonce(7);
+        assert!(matches!(
+            requested_signature(&requested, &sign(&filled)),
+            Err(SignerError::Incomplete)
+        ));
+
+        let signed = sign(&filled);
+        assert_eq!(
+            requested_signature(&filled, &signed).unwrap(),
+            signed.signature
+        );
+    }
+
+    #[test]
+    fn sends_transactions_to_the_wallet() {
+        let bridge = Bridge::new();
+        let (client, session) = connect(&bridge, true);
+        let hash = H256::repeat_byte(0x42);
+        bridge.respond(&session, json!(hash));
+
+        // NOTE: The mocked provider has no responses, so sending the
+        // transaction fails if it isn't sent to the wallet.
+        let (provider, _) = Provider::mocked();
+        let middleware = WalletMiddleware::new(provider, client).unwrap();
+        let request = TransactionRequest::new()
+            .to(Address::repeat_byte(2))
+            .value(1_000);
+        let pending = block_on(middleware.send_transaction(request, None)).unwrap();
+        assert_eq!(pending.tx_hash(), hash);
+    }
+}
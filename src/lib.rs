@@ -1,16 +1,23 @@
 #![allow(clippy::result_large_err)]
 
+#[cfg(feature = "alloy")]
+pub mod alloy;
+pub mod blocking;
 pub mod client;
 mod crypto;
 pub mod errors;
+#[cfg(feature = "ethers")]
+pub mod ethers;
 mod hex;
 mod protocol;
 #[cfg(feature = "qr")]
 pub mod qr;
 mod serialization;
+pub mod siwe;
 #[cfg(feature = "transport")]
 pub mod transport;
 mod uri;
+pub mod verify;
 
 pub use client::Client;
 pub use protocol::*;
@@ -1,8 +1,13 @@
 use crate::protocol::Topic;
 use crate::serialization;
-use ethers_core::types::{Address, U256};
+use ethers_core::types::transaction::eip1559::Eip1559TransactionRequest;
+use ethers_core::types::transaction::eip2718::TypedTransaction;
+use ethers_core::types::transaction::eip2930::{AccessList, Eip2930TransactionRequest};
+use ethers_core::types::{Address, Bytes, NameOrAddress, TransactionRequest, U256, U64};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::convert::TryFrom;
+use thiserror::Error;
 use url::Url;
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
@@ -54,14 +59,227 @@ pub struct Transaction {
     pub from: Address,
     #[serde(default, with = "serialization::emptynoneaddress")]
     pub to: Option<Address>,
-    #[serde(default)]
+    #[serde(default, alias = "gas", skip_serializing_if = "Option::is_none")]
     pub gas_limit: Option<U256>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub gas_price: Option<U256>,
     #[serde(default)]
     pub value: U256,
     #[serde(default, with = "serialization::prefixedhexstring")]
     pub data: Vec<u8>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub nonce: Option<U256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_fee_per_gas: Option<U256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_priority_fee_per_gas: Option<U256>,
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub transaction_type: Option<U64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_list: Option<AccessList>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<U64>,
+}
+
+impl Transaction {
+    /// The EIP-2718 type of EIP-2930 access list transactions.
+    pub const EIP2930_TYPE: u64 = 1;
+    /// The EIP-2718 type of EIP-1559 fee market transactions.
+    pub const EIP1559_TYPE: u64 = 2;
+
+    fn from_request(tx: TransactionRequest) -> Result<Self, TransactionConversionError> {
+        Ok(Transaction {
+            from: tx.from.ok_or(TransactionConversionError::MissingSender)?,
+            to: tx.to.map(resolved_address).transpose()?,
+            gas_limit: tx.gas,
+            gas_price: tx.gas_price,
+            value: tx.value.unwrap_or_default(),
+            data: tx.data.map(|data| data.to_vec()).unwrap_or_default(),
+            nonce: tx.nonce,
+            chain_id: tx.chain_id,
+            ..Default::default()
+        })
+    }
+}
+
+fn resolved_address(to: NameOrAddress) -> Result<Address, TransactionConversionError> {
+    match to {
+        NameOrAddress::Address(address) => Ok(address),
+        NameOrAddress::Name(name) => Err(TransactionConversionError::UnresolvedName(name)),
+    }
+}
+
+impl TryFrom<TransactionRequest> for Transaction {
+    type Error = TransactionConversionError;
+
+    fn try_from(tx: TransactionRequest) -> Result<Self, Self::Error> {
+        Transaction::from_request(tx)
+    }
+}
+
+impl TryFrom<Eip2930TransactionRequest> for Transaction {
+    type Error = TransactionConversionError;
+
+    fn try_from(tx: Eip2930TransactionRequest) -> Result<Self, Self::Error> {
+        Ok(Transaction {
+            transaction_type: Some(Transaction::EIP2930_TYPE.into()),
+            access_list: Some(tx.access_list),
+            ..Transaction::from_request(tx.tx)?
+        })
+    }
+}
+
+impl TryFrom<Eip1559TransactionRequest> for Transaction {
+    type Error = TransactionConversionError;
+
+    fn try_from(tx: Eip1559TransactionRequest) -> Result<Self, Self::Error> {
+        Ok(Transaction {
+            from: tx.from.ok_or(TransactionConversionError::MissingSender)?,
+            to: tx.to.map(resolved_address).transpose()?,
+            gas_limit: tx.gas,
+            gas_price: None,
+            value: tx.value.unwrap_or_default(),
+            data: tx.data.map(|data| data.to_vec()).unwrap_or_default(),
+            nonce: tx.nonce,
+            max_fee_per_gas: tx.max_fee_per_gas,
+            max_priority_fee_per_gas: tx.max_priority_fee_per_gas,
+            transaction_type: Some(Transaction::EIP1559_TYPE.into()),
+            access_list: Some(tx.access_list),
+            chain_id: tx.chain_id,
+        })
+    }
+}
+
+impl TryFrom<TypedTransaction> for Transaction {
+    type Error = TransactionConversionError;
+
+    fn try_from(tx: TypedTransaction) -> Result<Self, Self::Error> {
+        match tx {
+            TypedTransaction::Legacy(tx) => tx.try_into(),
+            TypedTransaction::Eip2930(tx) => tx.try_into(),
+            TypedTransaction::Eip1559(tx) => tx.try_into(),
+        }
+    }
+}
+
+impl From<Transaction> for TypedTransaction {
+    fn from(tx: Transaction) -> Self {
+        let transaction_type = tx.transaction_type.map(|kind| kind.as_u64());
+        let request = TransactionRequest {
+            from: Some(tx.from),
+            to: tx.to.map(NameOrAddress::Address),
+            gas: tx.gas_limit,
+            gas_price: tx.gas_price,
+            value: Some(tx.value),
+            data: Some(Bytes::from(tx.data)),
+            nonce: tx.nonce,
+            chain_id: tx.chain_id,
+        };
+
+        // NOTE: Untyped transactions that specify fee market fields are
+        // treated as EIP-1559 transactions, as wallets would.
+        let is_eip1559 = tx.max_fee_per_gas.is_some() || tx.max_priority_fee_per_gas.is_some();
+        match (transaction_type, is_eip1559) {
+            (Some(Transaction::EIP2930_TYPE), _) => TypedTransaction::Eip2930(
+                Eip2930TransactionRequest::new(request, tx.access_list.unwrap_or_default()),
+            ),
+            (Some(Transaction::EIP1559_TYPE), _) | (None, true) => {
+                TypedTransaction::Eip1559(Eip1559TransactionRequest {
+                    from: request.from,
+                    to: request.to,
+                    gas: request.gas,
+                    value: request.value,
+                    data: request.data,
+                    nonce: request.nonce,
+                    access_list: tx.access_list.unwrap_or_default(),
+                    max_priority_fee_per_gas: tx.max_priority_fee_per_gas,
+                    max_fee_per_gas: tx.max_fee_per_gas,
+                    chain_id: request.chain_id,
+                })
+            }
+            _ => TypedTransaction::Legacy(request),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Error, Eq, PartialEq)]
+pub enum TransactionConversionError {
+    #[error("transaction is missing a sender address")]
+    MissingSender,
+    #[error("transaction recipient '{0}' is an unresolved ENS name")]
+    UnresolvedName(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers_core::types::transaction::eip2930::AccessListItem;
+    use serde_json::json;
+
+    #[test]
+    fn eip1559_transaction_serialization() {
+        let tx = Transaction {
+            from: Address::repeat_byte(1),
+            to: Some(Address::repeat_byte(2)),
+            gas_limit: Some(21000.into()),
+            value: 1.into(),
+            max_fee_per_gas: Some(100.into()),
+            max_priority_fee_per_gas: Some(2.into()),
+            transaction_type: Some(Transaction::EIP1559_TYPE.into()),
+            access_list: Some(AccessList(vec![AccessListItem {
+                address: Address::repeat_byte(3),
+                storage_keys: vec![Default::default()],
+            }])),
+            chain_id: Some(1.into()),
+            ..Default::default()
+        };
+
+        let json = json!({
+            "from": "0x0101010101010101010101010101010101010101",
+            "to": "0x0202020202020202020202020202020202020202",
+            "gasLimit": "0x5208",
+            "value": "0x1",
+            "data": "0x",
+            "maxFeePerGas": "0x64",
+            "maxPriorityFeePerGas": "0x2",
+            "type": "0x2",
+            "accessList": [{
+                "address": "0x0303030303030303030303030303030303030303",
+                "storageKeys": [
+                    "0x0000000000000000000000000000000000000000000000000000000000000000",
+                ],
+            }],
+            "chainId": "0x1",
+        });
+        assert_eq!(serde_json::to_value(&tx).unwrap(), json);
+
+        let roundtrip = serde_json::from_value::<Transaction>(json).unwrap();
+        assert_eq!(roundtrip.max_fee_per_gas, tx.max_fee_per_gas);
+        assert_eq!(roundtrip.access_list, tx.access_list);
+    }
+
+    #[test]
+    fn typed_transaction_conversion() {
+        let typed = TypedTransaction::Eip1559(
+            Eip1559TransactionRequest::new()
+                .from(Address::repeat_byte(1))
+                .to(Address::repeat_byte(2))
+                .max_fee_per_gas(100)
+                .max_priority_fee_per_gas(2)
+                .chain_id(1),
+        );
+
+        let tx = Transaction::try_from(typed.clone()).unwrap();
+        assert_eq!(tx.transaction_type, Some(2.into()));
+        assert_eq!(tx.max_fee_per_gas, Some(100.into()));
+        assert_eq!(tx.gas_price, None);
+        let converted: TypedTransaction = tx.into();
+        assert_eq!(converted.sighash(), typed.sighash());
+
+        let legacy = TypedTransaction::Legacy(TransactionRequest::new().to("vitalik.eth"));
+        assert_eq!(
+            Transaction::try_from(legacy).unwrap_err(),
+            TransactionConversionError::MissingSender,
+        );
+    }
 }
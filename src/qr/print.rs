@@ -6,8 +6,9 @@ use termcolor::{ColorChoice, ColorSpec, StandardStream, WriteColor};
 use terminfo::capability::MaxColors;
 use terminfo::Database;
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
 pub enum Output {
+    #[default]
     Stdout,
     Stderr,
 }
@@ -29,12 +30,6 @@ fn default_color_choice(stream: Stream) -> ColorChoice {
     }
 }
 
-impl Default for Output {
-    fn default() -> Self {
-        Output::Stdout
-    }
-}
-
 #[derive(Clone, Debug)]
 pub struct Colors {
     pub black: Option<Color>,
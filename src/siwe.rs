@@ -0,0 +1,494 @@
+//! Sign-In With Ethereum (EIP-4361) messages.
+
+use crate::client::CallError;
+use crate::protocol::Metadata;
+use chrono::{DateTime, FixedOffset, SecondsFormat, Utc};
+use ethers_core::types::{Address, Signature, SignatureError};
+use ethers_core::utils::to_checksum;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::time::SystemTime;
+use thiserror::Error;
+use url::{Position, Url};
+
+const HEADER_SUFFIX: &str = " wants you to sign in with your Ethereum account:";
+const NONCE_LEN: usize = 17;
+
+/// An EIP-4361 Sign-In With Ethereum message.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Message {
+    pub scheme: Option<String>,
+    pub domain: String,
+    pub address: Address,
+    pub statement: Option<String>,
+    pub uri: Url,
+    pub version: String,
+    pub chain_id: u64,
+    pub nonce: String,
+    pub issued_at: DateTime<FixedOffset>,
+    pub expiration_time: Option<DateTime<FixedOffset>>,
+    pub not_before: Option<DateTime<FixedOffset>>,
+    pub request_id: Option<String>,
+    pub resources: Vec<Url>,
+}
+
+impl Message {
+    /// Creates a new sign-in message for the specified domain and account,
+    /// with a random nonce and issued now.
+    pub fn new(domain: impl Into<String>, address: Address, uri: Url, chain_id: u64) -> Self {
+        Message {
+            scheme: None,
+            domain: domain.into(),
+            address,
+            statement: None,
+            uri,
+            version: "1".to_owned(),
+            chain_id,
+            nonce: generate_nonce(),
+            issued_at: now().into(),
+            expiration_time: None,
+            not_before: None,
+            request_id: None,
+            resources: Vec::new(),
+        }
+    }
+
+    /// Creates a new sign-in message for a dapp's metadata. The message's
+    /// domain and URI are taken from the metadata URL.
+    pub fn for_metadata(meta: &Metadata, address: Address, chain_id: u64) -> Self {
+        let url = &meta.url;
+        let domain = &url[Position::BeforeHost..Position::AfterPort];
+        Message::new(domain, address, url.clone(), chain_id)
+    }
+
+    /// Verifies a signature for this message, checking the signer, domain,
+    /// nonce and validity period.
+    ///
+    /// The message is verified against its textual representation, so parsed
+    /// messages should be verified with [`verify`] and the original text.
+    pub fn verify(
+        &self,
+        signature: &Signature,
+        options: &VerifyOptions,
+    ) -> Result<(), VerificationError> {
+        self.verify_text(&self.to_string(), signature, options)
+    }
+
+    fn verify_text(
+        &self,
+        text: &str,
+        signature: &Signature,
+        options: &VerifyOptions,
+    ) -> Result<(), VerificationError> {
+        if let Some(domain) = &options.domain {
+            if *domain != self.domain {
+                return Err(VerificationError::Domain {
+                    expected: domain.clone(),
+                    actual: self.domain.clone(),
+                });
+            }
+        }
+        if let Some(nonce) = &options.nonce {
+            if *nonce != self.nonce {
+                return Err(VerificationError::Nonce);
+            }
+        }
+
+        let time = options.time.unwrap_or_else(now);
+        if let Some(expiration_time) = self.expiration_time {
+            if time >= expiration_time {
+                return Err(VerificationError::Expired(expiration_time));
+            }
+        }
+        if let Some(not_before) = self.not_before {
+            if time < not_before {
+                return Err(VerificationError::NotYetValid(not_before));
+            }
+        }
+
+        let signer = signature.recover(text)?;
+        if signer != self.address {
+            return Err(VerificationError::Signer {
+                expected: self.address,
+                actual: signer,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a signed sign-in message and verifies its signature.
+pub fn verify(
+    text: &str,
+    signature: &Signature,
+    options: &VerifyOptions,
+) -> Result<Message, VerificationError> {
+    let message = text.parse::<Message>()?;
+    message.verify_text(text, signature, options)?;
+    Ok(message)
+}
+
+fn now() -> DateTime<Utc> {
+    SystemTime::now().into()
+}
+
+/// Generates a random alphanumeric nonce for a sign-in message.
+pub fn generate_nonce() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(NONCE_LEN)
+        .map(char::from)
+        .collect()
+}
+
+/// The expected values when verifying a sign-in message.
+#[derive(Clone, Debug, Default)]
+pub struct VerifyOptions {
+    pub domain: Option<String>,
+    pub nonce: Option<String>,
+    /// The time to check the message's validity period against, defaults to
+    /// the current time.
+    pub time: Option<DateTime<Utc>>,
+}
+
+impl VerifyOptions {
+    /// Returns the verification options expecting the domain and nonce of the
+    /// specified message.
+    pub fn for_message(message: &Message) -> Self {
+        VerifyOptions {
+            domain: Some(message.domain.clone()),
+            nonce: Some(message.nonce.clone()),
+            time: None,
+        }
+    }
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let Some(scheme) = &self.scheme {
+            write!(f, "{}://", scheme)?;
+        }
+        writeln!(f, "{}{}", self.domain, HEADER_SUFFIX)?;
+        writeln!(f, "{}", to_checksum(&self.address, None))?;
+        writeln!(f)?;
+        if let Some(statement) = &self.statement {
+            writeln!(f, "{}", statement)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "URI: {}", self.uri)?;
+        writeln!(f, "Version: {}", self.version)?;
+        writeln!(f, "Chain ID: {}", self.chain_id)?;
+        writeln!(f, "Nonce: {}", self.nonce)?;
+        write!(f, "Issued At: {}", timestamp(&self.issued_at))?;
+        if let Some(expiration_time) = &self.expiration_time {
+            write!(f, "\nExpiration Time: {}", timestamp(expiration_time))?;
+        }
+        if let Some(not_before) = &self.not_before {
+            write!(f, "\nNot Before: {}", timestamp(not_before))?;
+        }
+        if let Some(request_id) = &self.request_id {
+            write!(f, "\nRequest ID: {}", request_id)?;
+        }
+        if !self.resources.is_empty() {
+            write!(f, "\nResources:")?;
+            for resource in &self.resources {
+                write!(f, "\n- {}", resource)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn timestamp(time: &DateTime<FixedOffset>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+impl FromStr for Message {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.split('\n').peekable();
+
+        let origin = lines
+            .next()
+            .and_then(|line| line.strip_suffix(HEADER_SUFFIX))
+            .ok_or(ParseError::Invalid("header"))?;
+        let (scheme, domain) = match origin.split_once("://") {
+            Some((scheme, domain)) => (Some(scheme.to_owned()), domain),
+            None => (None, origin),
+        };
+        if domain.is_empty() {
+            return Err(ParseError::Invalid("domain"));
+        }
+
+        let address = lines.next().ok_or(ParseError::Missing("address"))?;
+        if !address.starts_with("0x") {
+            return Err(ParseError::Invalid("address"));
+        }
+        let address = address
+            .parse::<Address>()
+            .map_err(|_| ParseError::Invalid("address"))?;
+        if lines.next() != Some("") {
+            return Err(ParseError::Invalid("address"));
+        }
+
+        // NOTE: The statement is optional, and some implementations omit the
+        // empty line that follows it when there is no statement.
+        let statement = match lines.peek() {
+            Some(line) if line.starts_with("URI: ") => None,
+            Some(&"") => {
+                lines.next();
+                None
+            }
+            Some(_) => {
+                let statement = lines.next().map(str::to_owned);
+                if lines.next() != Some("") {
+                    return Err(ParseError::Invalid("statement"));
+                }
+                statement
+            }
+            None => return Err(ParseError::Missing("URI")),
+        };
+
+        let uri = field(&mut lines, "URI")?
+            .ok_or(ParseError::Missing("URI"))?
+            .parse()
+            .map_err(|_| ParseError::Invalid("URI"))?;
+        let version = field(&mut lines, "Version")?.ok_or(ParseError::Missing("Version"))?;
+        if version != "1" {
+            return Err(ParseError::Invalid("Version"));
+        }
+        let chain_id = field(&mut lines, "Chain ID")?
+            .ok_or(ParseError::Missing("Chain ID"))?
+            .parse()
+            .map_err(|_| ParseError::Invalid("Chain ID"))?;
+        let nonce = field(&mut lines, "Nonce")?.ok_or(ParseError::Missing("Nonce"))?;
+        if nonce.len() < 8 || !nonce.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(ParseError::Invalid("Nonce"));
+        }
+        let issued_at = field(&mut lines, "Issued At")?
+            .ok_or(ParseError::Missing("Issued At"))
+            .and_then(|value| parse_timestamp(value, "Issued At"))?;
+        let expiration_time = field(&mut lines, "Expiration Time")?
+            .map(|value| parse_timestamp(value, "Expiration Time"))
+            .transpose()?;
+        let not_before = field(&mut lines, "Not Before")?
+            .map(|value| parse_timestamp(value, "Not Before"))
+            .transpose()?;
+        let request_id = field(&mut lines, "Request ID")?.map(str::to_owned);
+
+        let mut resources = Vec::new();
+        if lines.peek() == Some(&"Resources:") {
+            lines.next();
+            for line in lines.by_ref() {
+                let resource = line
+                    .strip_prefix("- ")
+                    .and_then(|resource| resource.parse().ok())
+                    .ok_or(ParseError::Invalid("Resources"))?;
+                resources.push(resource);
+            }
+        }
+        if lines.next().is_some() {
+            return Err(ParseError::TrailingData);
+        }
+
+        Ok(Message {
+            scheme,
+            domain: domain.to_owned(),
+            address,
+            statement,
+            uri,
+            version: version.to_owned(),
+            chain_id,
+            nonce: nonce.to_owned(),
+            issued_at,
+            expiration_time,
+            not_before,
+            request_id,
+            resources,
+        })
+    }
+}
+
+/// Reads the value of the next line if it is the specified field.
+fn field<'a>(
+    lines: &mut std::iter::Peekable<impl Iterator<Item = &'a str>>,
+    name: &'static str,
+) -> Result<Option<&'a str>, ParseError> {
+    let value = match lines
+        .peek()
+        .and_then(|line| line.strip_prefix(name))
+        .and_then(|line| line.strip_prefix(": "))
+    {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    lines.next();
+
+    if value.is_empty() {
+        return Err(ParseError::Invalid(name));
+    }
+    Ok(Some(value))
+}
+
+fn parse_timestamp(value: &str, name: &'static str) -> Result<DateTime<FixedOffset>, ParseError> {
+    DateTime::parse_from_rfc3339(value).map_err(|_| ParseError::Invalid(name))
+}
+
+#[derive(Clone, Debug, Error, Eq, PartialEq)]
+pub enum ParseError {
+    #[error("sign-in message is missing the {0} field")]
+    Missing(&'static str),
+    #[error("sign-in message has an invalid {0} field")]
+    Invalid(&'static str),
+    #[error("sign-in message has unexpected trailing data")]
+    TrailingData,
+}
+
+#[derive(Debug, Error)]
+pub enum VerificationError {
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error("sign-in message is for domain '{actual}' instead of '{expected}'")]
+    Domain { expected: String, actual: String },
+    #[error("sign-in message nonce does not match")]
+    Nonce,
+    #[error("sign-in message expired at {0}")]
+    Expired(DateTime<FixedOffset>),
+    #[error("sign-in message is not valid before {0}")]
+    NotYetValid(DateTime<FixedOffset>),
+    #[error("failed to recover signer: {0}")]
+    Signature(#[from] SignatureError),
+    #[error("sign-in message signed by {actual:?} instead of {expected:?}")]
+    Signer { expected: Address, actual: Address },
+}
+
+#[derive(Debug, Error)]
+pub enum SignInError {
+    #[error(transparent)]
+    Call(#[from] CallError),
+    #[error("malformed sign-in signature: {0}")]
+    Signature(#[from] SignatureError),
+    #[error(transparent)]
+    Verification(#[from] VerificationError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify::tests::{sign_message, signing_key};
+    use ethers_core::utils::secret_key_to_address;
+
+    const MESSAGE: &str = "\
+service.invalid wants you to sign in with your Ethereum account:
+0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2
+
+I accept the ServiceOrg Terms of Service: https://service.invalid/tos
+
+URI: https://service.invalid/login
+Version: 1
+Chain ID: 1
+Nonce: 32891756
+Issued At: 2021-09-30T16:25:24Z
+Expiration Time: 2021-10-30T16:25:24Z
+Resources:
+- ipfs://bafybeiemxf5abjwjbikoz4mc3a3dla6ual3jsgpdr4cjr3oz3evfyavhwq/
+- https://example.com/my-web2-claim.json";
+
+    #[test]
+    fn message_roundtrip() {
+        let message = MESSAGE.parse::<Message>().unwrap();
+        assert_eq!(message.domain, "service.invalid");
+        assert_eq!(message.nonce, "32891756");
+        assert_eq!(message.resources.len(), 2);
+        assert_eq!(message.to_string(), MESSAGE);
+
+        let message = Message {
+            statement: None,
+            ..message
+        };
+        assert_eq!(message.to_string().parse::<Message>().unwrap(), message);
+
+        assert_eq!(
+            MESSAGE
+                .replace("Nonce: 32891756", "Nonce: 1234")
+                .parse::<Message>(),
+            Err(ParseError::Invalid("Nonce"))
+        );
+        assert_eq!(
+            MESSAGE.replace("Version: 1\n", "").parse::<Message>(),
+            Err(ParseError::Missing("Version"))
+        );
+    }
+
+    #[test]
+    fn message_for_metadata() {
+        let meta = Metadata {
+            description: "walletconnect-rs tests".into(),
+            url: "https://localhost:8080/login".parse().unwrap(),
+            icons: vec![],
+            name: "walletconnect-rs".into(),
+        };
+        let message = Message::for_metadata(&meta, Address::zero(), 100);
+        assert_eq!(message.domain, "localhost:8080");
+        assert_eq!(message.uri, meta.url);
+        assert_eq!(message.nonce.len(), NONCE_LEN);
+    }
+
+    #[test]
+    fn verifies_sign_in_messages() {
+        let key = signing_key();
+        let message = Message {
+            expiration_time: Some("2021-10-30T16:25:24Z".parse().unwrap()),
+            ..Message::new(
+                "service.invalid",
+                secret_key_to_address(&key),
+                "https://service.invalid/login".parse().unwrap(),
+                1,
+            )
+        };
+        let text = message.to_string();
+        let signature = sign_message(&key, &text);
+
+        let options = VerifyOptions {
+            time: Some("2021-10-01T00:00:00Z".parse().unwrap()),
+            ..VerifyOptions::for_message(&message)
+        };
+        assert_eq!(verify(&text, &signature, &options).unwrap(), message);
+
+        assert!(matches!(
+            verify(
+                &text,
+                &signature,
+                &VerifyOptions {
+                    domain: Some("phishing.invalid".into()),
+                    ..options.clone()
+                }
+            ),
+            Err(VerificationError::Domain { .. })
+        ));
+        assert!(matches!(
+            verify(
+                &text,
+                &signature,
+                &VerifyOptions {
+                    nonce: Some("reused-nonce".into()),
+                    ..options.clone()
+                }
+            ),
+            Err(VerificationError::Nonce)
+        ));
+        assert!(matches!(
+            message.verify(&signature, &VerifyOptions::for_message(&message)),
+            Err(VerificationError::Expired(_))
+        ));
+        assert!(matches!(
+            verify(&text, &sign_message(&key, MESSAGE), &options),
+            Err(VerificationError::Signer { .. })
+        ));
+    }
+}
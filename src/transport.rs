@@ -1,67 +1,161 @@
-use crate::client::{Client, ConnectorError, NotConnectedError, SessionError};
+mod fill;
+mod http;
+mod pending;
+mod provider;
+#[cfg(feature = "proxy")]
+mod proxy;
+mod signature;
+
+pub use self::fill::{fill_transaction, Filler};
+pub use self::http::{HttpTransportError, HttpTransportFactory, CHAIN_ID_PLACEHOLDER};
+pub use self::pending::{PendingTransaction, PendingTransactionError, DEFAULT_POLL_INTERVAL};
+pub use self::provider::{Provider, ProviderEvent, ProviderRpcError, RequestArguments};
+#[cfg(feature = "proxy")]
+pub use self::proxy::Proxy;
+pub use self::signature::is_valid_signature;
+use crate::client::{
+    is_wallet_method, wallet_method, CallError, Client, ConnectorError, NotConnectedError,
+    SessionError,
+};
 use crate::protocol::Transaction;
-use ethers_core::types::Address;
+use ethers_core::types::{Address, H256};
 use futures::future::{BoxFuture, FutureExt};
 use jsonrpc_core::{Call, MethodCall, Params};
 use serde::Deserialize;
 use serde_json::{json, Value};
 use std::error::Error;
-use std::sync::Arc;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::{Arc, Mutex, MutexGuard};
 use thiserror::Error;
+use web3::api::SubscriptionId;
 use web3::transports::Http;
-use web3::types::U64;
-use web3::{helpers, RequestId, Transport};
+use web3::{helpers, BatchTransport, DuplexTransport, RequestId, Transport};
 
 pub trait TransportFactory {
     type Transport: Transport;
     type Error: Error;
 
+    #[allow(clippy::wrong_self_convention)]
     fn new(&mut self, chain_id: u64) -> Result<Self::Transport, Self::Error>;
 }
 
-struct InfuraTransportFactory(String);
-
-impl TransportFactory for InfuraTransportFactory {
-    type Transport = Http;
-    type Error = web3::Error;
-
-    fn new(&mut self, chain_id: u64) -> Result<Self::Transport, Self::Error> {
-        let network = match chain_id {
-            1 => "mainnet",
-            3 => "ropsten",
-            4 => "rinkeby",
-            5 => "goerli",
-            42 => "kovan",
-            _ => {
-                return Err(web3::Error::Transport(
-                    web3::error::TransportError::Message(format!(
-                        "unknown chain ID '{}'",
-                        chain_id
-                    )),
-                ))
-            }
-        };
-        let url = format!("https://{}.infura.io/v3/{}", network, self.0);
-        let http = Http::new(&url)?;
-
-        Ok(http)
-    }
-}
-
 #[derive(Clone, Debug)]
 pub struct WalletConnect<T>(Arc<Inner<T>>);
 
 #[derive(Debug)]
 struct Inner<T> {
     client: Client,
-    accounts: Vec<Address>,
+    node: Mutex<Node<T>>,
+    fillers: Mutex<Vec<Filler>>,
+}
+
+type BoxedFactory<T> = Box<dyn FnMut(u64) -> Result<T, FactoryError> + Send>;
+type FactoryError = Box<dyn Error + Send + Sync>;
+
+/// The node transport for the chain that the session was last connected to,
+/// along with the factory for creating transports for other chains.
+struct Node<T> {
+    factory: BoxedFactory<T>,
     chain_id: u64,
     transport: T,
 }
 
+impl<T> Debug for Node<T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Node")
+            .field("chain_id", &self.chain_id)
+            .field("transport", &self.transport)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Inner<T>
+where
+    T: Transport,
+{
+    fn lock_node(&self) -> MutexGuard<'_, Node<T>> {
+        self.node
+            .lock()
+            .expect("mutex guard should never be poisoned")
+    }
+
+    /// Returns the node transport for the session's current chain, creating a
+    /// new one if the wallet switched chains.
+    ///
+    /// Node requests don't need the wallet, so the last used node transport
+    /// keeps being used while the session is disconnected.
+    fn node(&self) -> Result<T, TransportError> {
+        let chain_id = self.client.accounts().ok().map(|(_, chain_id)| chain_id);
+
+        let mut node = self.lock_node();
+        if let Some(chain_id) = chain_id.filter(|chain_id| *chain_id != node.chain_id) {
+            log::debug!("session switched to chain {}, updating node", chain_id);
+            node.transport = (node.factory)(chain_id).map_err(TransportError::Transport)?;
+            node.chain_id = chain_id;
+        }
+
+        Ok(node.transport.clone())
+    }
+
+    fn fillers(&self) -> Vec<Filler> {
+        self.fillers
+            .lock()
+            .expect("mutex guard should never be poisoned")
+            .clone()
+    }
+
+    /// Fills in missing transaction fields with the configured fillers.
+    async fn fill(&self, transaction: &mut Transaction) -> Result<(), web3::Error> {
+        let fillers = self.fillers();
+        if fillers.is_empty() {
+            return Ok(());
+        }
+
+        let transport = self.node().map_err(node_error)?;
+        fill_transaction(&transport, &fillers, transaction).await
+    }
+
+    /// Handles an account or signing request with the wallet.
+    async fn wallet_call(&self, method: &str, params: Params) -> Result<Value, web3::Error> {
+        let mut params = match params {
+            Params::Array(params) => params,
+            Params::None => Vec::new(),
+            Params::Map(_) => {
+                return Err(web3::Error::Decoder(format!(
+                    "expected positional parameters for '{}'",
+                    method
+                )))
+            }
+        };
+
+        if let ("eth_sendTransaction" | "eth_signTransaction", Some(transaction)) =
+            (wallet_method(method), params.first_mut())
+        {
+            let mut filled = Transaction::deserialize(&*transaction)?;
+            self.fill(&mut filled).await?;
+            *transaction = json!(filled);
+        }
+
+        self.client
+            .wallet_request(method, params)
+            .await
+            .map_err(call_error)
+    }
+}
+
 impl WalletConnect<Http> {
-    pub fn new(client: Client, infura_id: impl Into<String>) -> Result<Self, TransportError> {
-        WalletConnect::with_factory(client, InfuraTransportFactory(infura_id.into()))
+    /// Creates a new WalletConnect transport using the default public RPC
+    /// endpoints for the session's chain.
+    pub fn new(client: Client) -> Result<Self, TransportError> {
+        WalletConnect::with_factory(client, HttpTransportFactory::default())
+    }
+
+    /// Creates a new WalletConnect transport using Infura RPC endpoints.
+    pub fn infura(client: Client, project_id: &str) -> Result<Self, TransportError> {
+        WalletConnect::with_factory(client, HttpTransportFactory::infura(project_id))
     }
 }
 
@@ -69,26 +163,104 @@ impl<T> WalletConnect<T>
 where
     T: Transport,
 {
+    /// Creates a new WalletConnect transport that uses node transports created
+    /// by the specified factory.
+    ///
+    /// The transport follows session updates from the wallet, and creates a
+    /// new node transport whenever the wallet switches chains.
     pub fn with_factory<F>(client: Client, mut factory: F) -> Result<Self, TransportError>
     where
-        F: TransportFactory<Transport = T>,
-        F::Error: 'static,
+        F: TransportFactory<Transport = T> + Send + 'static,
+        F::Error: Send + Sync + 'static,
     {
-        let (accounts, chain_id) = client.accounts()?;
-        let transport = factory
-            .new(chain_id)
-            .map_err(|err| TransportError::Transport(Box::new(err)))?;
+        let (_, chain_id) = client.accounts()?;
+        let mut factory: BoxedFactory<T> =
+            Box::new(move |chain_id| factory.new(chain_id).map_err(FactoryError::from));
+        let transport = factory(chain_id).map_err(TransportError::Transport)?;
 
         Ok(WalletConnect(Arc::new(Inner {
             client,
-            accounts,
-            chain_id,
-            transport,
+            node: Mutex::new(Node {
+                factory,
+                chain_id,
+                transport,
+            }),
+            fillers: Mutex::new(Vec::new()),
         })))
     }
 
-    pub fn accounts(&self) -> (Vec<Address>, u64) {
-        (self.0.accounts.clone(), self.0.chain_id)
+    /// Returns the session's current accounts and chain ID.
+    pub fn accounts(&self) -> Result<(Vec<Address>, u64), NotConnectedError> {
+        self.0.client.accounts()
+    }
+
+    /// Sets the fillers that are used to fill in missing fields of
+    /// transactions before they are sent to the wallet.
+    ///
+    /// No fillers are used by default, leaving it up to the wallet to fill in
+    /// missing fields.
+    pub fn set_fillers(&self, fillers: impl IntoIterator<Item = Filler>) {
+        *self
+            .0
+            .fillers
+            .lock()
+            .expect("mutex guard should never be poisoned") = fillers.into_iter().collect();
+    }
+
+    /// Fills in missing transaction fields with the configured fillers.
+    pub async fn fill_transaction(&self, transaction: &mut Transaction) -> Result<(), web3::Error> {
+        self.0.fill(transaction).await
+    }
+
+    /// Fills in and sends a transaction with the wallet, returning a pending
+    /// transaction for waiting on its receipt with the node transport.
+    pub async fn send_transaction(
+        &self,
+        mut transaction: Transaction,
+    ) -> Result<PendingTransaction<T>, web3::Error> {
+        self.0.fill(&mut transaction).await?;
+        let transport = self.0.node().map_err(node_error)?;
+
+        let (from, nonce) = (transaction.from, transaction.nonce);
+        let hash = self
+            .0
+            .client
+            .send_transaction(transaction)
+            .await
+            .map_err(call_error)?;
+
+        let pending = PendingTransaction::new(transport, hash, from);
+        Ok(match nonce {
+            Some(nonce) => pending.nonce(nonce),
+            None => pending,
+        })
+    }
+
+    /// Returns a pending transaction for waiting on the receipt of a
+    /// transaction that was already sent.
+    pub fn pending_transaction(
+        &self,
+        hash: H256,
+        from: Address,
+    ) -> Result<PendingTransaction<T>, TransportError> {
+        Ok(PendingTransaction::new(self.0.node()?, hash, from))
+    }
+
+    /// Returns the underlying node transport for the session's current chain.
+    pub fn transport(&self) -> Result<T, TransportError> {
+        self.0.node()
+    }
+
+    /// Verifies a signature for a message hash with the node transport,
+    /// supporting both ECDSA and smart-contract wallet signatures.
+    pub async fn is_valid_signature(
+        &self,
+        signer: Address,
+        hash: H256,
+        signature: &[u8],
+    ) -> Result<bool, web3::Error> {
+        let transport = self.0.node().map_err(node_error)?;
+        is_valid_signature(&transport, signer, hash, signature).await
     }
 }
 
@@ -101,7 +273,7 @@ pub enum TransportError {
     #[error("connection unexpectedly dropped: {0}")]
     ConnectionDropped(#[from] NotConnectedError),
     #[error("error creating transport: {0}")]
-    Transport(Box<dyn Error>),
+    Transport(Box<dyn Error + Send + Sync>),
 }
 
 impl<T> Transport for WalletConnect<T>
@@ -113,45 +285,471 @@ where
 
     fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
         log::trace!("preparing call '{}' {:?}", method, params);
-        match method {
-            "eth_accounts" | "eth_chainId" | "eth_sendTransaction" => {
-                (0, helpers::build_request(0, method, params))
-            }
-            _ => self.0.transport.prepare(method, params),
+        if is_wallet_method(method) {
+            (0, helpers::build_request(0, method, params))
+        } else {
+            // NOTE: Requests are prepared with the last used node transport,
+            // the transport for the session's current chain is only looked up
+            // when the request is sent.
+            self.0.lock_node().transport.prepare(method, params)
         }
     }
 
     fn send(&self, id: RequestId, request: Call) -> Self::Out {
         let inner = self.0.clone();
         async move {
-            match request {
-                Call::MethodCall(MethodCall { method, .. }) if method == "eth_accounts" => {
-                    Ok(json!(inner.accounts))
+            match wallet_call(request) {
+                Ok((method, params)) => inner.wallet_call(&method, params).await,
+                Err(request) => {
+                    let transport = inner.node().map_err(node_error)?;
+                    transport.send(id, request).await
                 }
-                Call::MethodCall(MethodCall { method, .. }) if method == "eth_chainId" => {
-                    Ok(json!(U64::from(inner.chain_id)))
+            }
+        }
+        .boxed()
+    }
+}
+
+impl<T> BatchTransport for WalletConnect<T>
+where
+    T: BatchTransport + Send + Sync + 'static,
+    T::Out: Send,
+    T::Batch: Send,
+{
+    type Batch = BoxFuture<'static, Result<Vec<Result<Value, web3::Error>>, web3::Error>>;
+
+    /// Sends a batch of requests, returning the results in request order.
+    ///
+    /// Note that wallet requests are always sent before node requests,
+    /// regardless of their position in the batch.
+    fn send_batch<I>(&self, requests: I) -> Self::Batch
+    where
+        I: IntoIterator<Item = (RequestId, Call)>,
+    {
+        let inner = self.0.clone();
+        let requests = requests.into_iter().collect::<Vec<_>>();
+        async move {
+            // NOTE: Wallet requests are handled one at a time, while all node
+            // requests are sent to the node transport as a single batch. The
+            // results are then merged back into request order.
+            let mut results = Vec::with_capacity(requests.len());
+            let mut node_requests = Vec::new();
+            for (id, request) in requests {
+                match wallet_call(request) {
+                    Ok((method, params)) => {
+                        results.push(Some(inner.wallet_call(&method, params).await));
+                    }
+                    Err(request) => {
+                        results.push(None);
+                        node_requests.push((id, request));
+                    }
                 }
-                Call::MethodCall(MethodCall {
-                    method,
-                    params: Params::Array(params),
-                    ..
-                }) if method == "eth_sendTransaction" && !params.is_empty() => {
-                    log::trace!(">>{}", params[0]);
-                    let transaction = Transaction::deserialize(&params[0])?;
-                    let tx = inner
-                        .client
-                        .send_transaction(transaction)
-                        .await
-                        .map_err(|err| {
-                            web3::Error::Transport(web3::error::TransportError::Message(
-                                err.to_string(),
-                            ))
-                        })?;
-                    Ok(json!(tx))
+            }
+
+            if !node_requests.is_empty() {
+                let transport = inner.node().map_err(node_error)?;
+                let mut node_results = transport.send_batch(node_requests).await?.into_iter();
+                for result in results.iter_mut().filter(|result| result.is_none()) {
+                    *result = Some(node_results.next().ok_or_else(|| {
+                        web3::Error::InvalidResponse("missing batch response".to_owned())
+                    })?);
                 }
-                request => inner.transport.send(id, request).await,
             }
+
+            Ok(results.into_iter().flatten().collect())
         }
         .boxed()
     }
 }
+
+impl<T> DuplexTransport for WalletConnect<T>
+where
+    T: DuplexTransport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    type NotificationStream = T::NotificationStream;
+
+    // NOTE: Subscriptions are managed by the node transport that handled the
+    // `eth_subscribe` request, which is the last used node transport. This
+    // means that subscriptions don't carry over when the wallet switches
+    // chains.
+
+    fn subscribe(&self, id: SubscriptionId) -> Result<Self::NotificationStream, web3::Error> {
+        self.0.lock_node().transport.subscribe(id)
+    }
+
+    fn unsubscribe(&self, id: SubscriptionId) -> Result<(), web3::Error> {
+        self.0.lock_node().transport.unsubscribe(id)
+    }
+}
+
+/// Returns the method and parameters of requests that are handled by the
+/// wallet, or the original request for requests that are sent to the node.
+fn wallet_call(request: Call) -> Result<(String, Params), Call> {
+    match request {
+        Call::MethodCall(MethodCall { method, params, .. }) if is_wallet_method(&method) => {
+            Ok((method, params))
+        }
+        request => Err(request),
+    }
+}
+
+/// Converts an error retrieving the node transport into a Web3 error.
+fn node_error(err: TransportError) -> web3::Error {
+    web3::Error::Transport(web3::error::TransportError::Message(err.to_string()))
+}
+
+/// Converts a client call error into a Web3 error, preserving wallet JSON RPC
+/// errors so that their error codes can be classified.
+fn call_error(err: impl Into<CallError>) -> web3::Error {
+    let err = err.into();
+    match err {
+        CallError::Rpc(err) => web3::Error::Rpc(err),
+        err => web3::Error::Transport(web3::error::TransportError::Message(err.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{connect, Bridge};
+    use futures::channel::mpsc as channel;
+    use futures::executor::block_on;
+    use futures::future::{self, Ready};
+    use futures::StreamExt;
+    use std::collections::BTreeMap;
+    use std::convert::Infallible;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    type Handler = dyn Fn(&str, &[Value]) -> Result<Value, web3::Error> + Send + Sync;
+    type Requests = Vec<(RequestId, String, Vec<Value>)>;
+    type Subscriptions = BTreeMap<SubscriptionId, channel::UnboundedSender<Value>>;
+
+    /// A stand-in node transport that answers requests with a handler, and
+    /// records every request and subscription that it receives.
+    #[derive(Clone)]
+    pub struct StandIn {
+        handler: Arc<Handler>,
+        requests: Arc<Mutex<Requests>>,
+        subscriptions: Arc<Mutex<Subscriptions>>,
+    }
+
+    impl StandIn {
+        pub fn new(
+            handler: impl Fn(&str, &[Value]) -> Result<Value, web3::Error> + Send + Sync + 'static,
+        ) -> Self {
+            StandIn {
+                handler: Arc::new(handler),
+                requests: Default::default(),
+                subscriptions: Default::default(),
+            }
+        }
+
+        /// Returns the IDs of the requests that were sent, in order.
+        #[cfg(feature = "proxy")]
+        pub fn ids(&self) -> Vec<RequestId> {
+            let requests = self.requests.lock().unwrap();
+            requests.iter().map(|(id, _, _)| *id).collect()
+        }
+
+        /// Returns the methods of the requests that were sent, in order.
+        pub fn methods(&self) -> Vec<String> {
+            let requests = self.requests.lock().unwrap();
+            requests
+                .iter()
+                .map(|(_, method, _)| method.clone())
+                .collect()
+        }
+
+        /// Returns the parameters of each request for the specified method.
+        pub fn params(&self, method: &str) -> Vec<Vec<Value>> {
+            let requests = self.requests.lock().unwrap();
+            requests
+                .iter()
+                .filter(|(_, m, _)| m == method)
+                .map(|(_, _, params)| params.clone())
+                .collect()
+        }
+
+        /// Returns the IDs of the active subscriptions.
+        pub fn subscriptions(&self) -> Vec<SubscriptionId> {
+            let subscriptions = self.subscriptions.lock().unwrap();
+            subscriptions.keys().cloned().collect()
+        }
+
+        /// Sends a notification to an active subscription.
+        pub fn notify(&self, id: &SubscriptionId, notification: Value) {
+            let subscriptions = self.subscriptions.lock().unwrap();
+            subscriptions[id].unbounded_send(notification).unwrap();
+        }
+
+        fn call(&self, id: RequestId, request: Call) -> Result<Value, web3::Error> {
+            let (method, params) = match request {
+                Call::MethodCall(MethodCall { method, params, .. }) => (method, params),
+                _ => unreachable!(),
+            };
+            let params = match params {
+                Params::Array(params) => params,
+                Params::None => Vec::new(),
+                Params::Map(params) => vec![Value::Object(params)],
+            };
+
+            let result = (self.handler)(&method, &params);
+            self.requests.lock().unwrap().push((id, method, params));
+            result
+        }
+    }
+
+    impl Debug for StandIn {
+        fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+            f.debug_struct("StandIn").finish_non_exhaustive()
+        }
+    }
+
+    impl Transport for StandIn {
+        type Out = Ready<Result<Value, web3::Error>>;
+
+        fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+            (0, helpers::build_request(0, method, params))
+        }
+
+        fn send(&self, id: RequestId, request: Call) -> Self::Out {
+            future::ready(self.call(id, request))
+        }
+    }
+
+    impl BatchTransport for StandIn {
+        type Batch = Ready<Result<Vec<Result<Value, web3::Error>>, web3::Error>>;
+
+        fn send_batch<I>(&self, requests: I) -> Self::Batch
+        where
+            I: IntoIterator<Item = (RequestId, Call)>,
+        {
+            let results = requests
+                .into_iter()
+                .map(|(id, request)| self.call(id, request))
+                .collect();
+            future::ready(Ok(results))
+        }
+    }
+
+    impl DuplexTransport for StandIn {
+        type NotificationStream = channel::UnboundedReceiver<Value>;
+
+        fn subscribe(&self, id: SubscriptionId) -> Result<Self::NotificationStream, web3::Error> {
+            let (tx, rx) = channel::unbounded();
+            self.subscriptions.lock().unwrap().insert(id, tx);
+            Ok(rx)
+        }
+
+        fn unsubscribe(&self, id: SubscriptionId) -> Result<(), web3::Error> {
+            self.subscriptions.lock().unwrap().remove(&id);
+            Ok(())
+        }
+    }
+
+    /// A stand-in node is a factory for itself, for tests that use the same
+    /// node for every chain.
+    impl TransportFactory for StandIn {
+        type Transport = StandIn;
+        type Error = Infallible;
+
+        fn new(&mut self, _: u64) -> Result<StandIn, Infallible> {
+            Ok(self.clone())
+        }
+    }
+
+    /// A factory for stand-in nodes that answer requests with their method and
+    /// chain ID, recording the chains that nodes were created for.
+    #[derive(Clone, Default)]
+    struct Factory(Arc<Mutex<Vec<u64>>>);
+
+    impl TransportFactory for Factory {
+        type Transport = StandIn;
+        type Error = Infallible;
+
+        fn new(&mut self, chain_id: u64) -> Result<StandIn, Infallible> {
+            self.0.lock().unwrap().push(chain_id);
+            Ok(StandIn::new(move |method, _| {
+                Ok(json!({ "method": method, "chainId": chain_id }))
+            }))
+        }
+    }
+
+    /// Starts a stand-in HTTP node that answers requests with a result,
+    /// returning its URL along with a channel of the requests it received.
+    fn http_node(result: Value) -> (String, mpsc::Receiver<Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (requests, received) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = BufReader::new(stream.unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).unwrap();
+                    match line.split_once(':') {
+                        Some((name, value)) if name.eq_ignore_ascii_case("content-length") => {
+                            length = value.trim().parse().unwrap();
+                        }
+                        _ if line.trim().is_empty() => break,
+                        _ => {}
+                    }
+                }
+                let mut body = vec![0; length];
+                stream.read_exact(&mut body).unwrap();
+
+                let request = serde_json::from_slice::<Value>(&body).unwrap();
+                let response = json!({
+                    "jsonrpc": "2.0",
+                    "result": result,
+                    "id": request["id"],
+                })
+                .to_string();
+                let _ = requests.send(request);
+                write!(
+                    stream.get_mut(),
+                    "HTTP/1.1 200 OK\r\n\
+                     Content-Type: application/json\r\n\
+                     Content-Length: {}\r\n\
+                     Connection: close\r\n\
+                     \r\n\
+                     {}",
+                    response.len(),
+                    response,
+                )
+                .unwrap();
+            }
+        });
+        (url, received)
+    }
+
+    fn wait_for(condition: impl Fn() -> bool) {
+        let start = Instant::now();
+        while !condition() {
+            assert!(start.elapsed() < Duration::from_secs(10), "timed out");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn follows_chain_switches() {
+        let bridge = Bridge::new();
+        let (client, session) = connect(&bridge, true);
+        let factory = Factory::default();
+        let transport = WalletConnect::with_factory(client, factory.clone()).unwrap();
+        let chain_id = || {
+            let result = block_on(transport.execute("eth_blockNumber", vec![])).unwrap();
+            result["chainId"].clone()
+        };
+        assert_eq!(chain_id(), json!(1));
+
+        bridge.update(&session, true, 100);
+        wait_for(|| matches!(transport.accounts(), Ok((_, 100))));
+        assert_eq!(chain_id(), json!(100));
+
+        // NOTE: Node requests keep using the last node transport once the
+        // session is disconnected.
+        bridge.update(&session, false, 100);
+        wait_for(|| transport.accounts().is_err());
+        assert_eq!(chain_id(), json!(100));
+        assert_eq!(*factory.0.lock().unwrap(), [1, 100]);
+    }
+
+    #[test]
+    fn batches_wallet_and_node_requests() {
+        let (client, session) = connect(&Bridge::new(), true);
+        let transport = WalletConnect::with_factory(client, Factory::default()).unwrap();
+        let requests = [
+            "eth_blockNumber",
+            "eth_accounts",
+            "eth_gasPrice",
+            "eth_chainId",
+        ]
+        .into_iter()
+        .map(|method| transport.prepare(method, vec![]))
+        .collect::<Vec<_>>();
+        let results = block_on(transport.send_batch(requests))
+            .unwrap()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            results,
+            [
+                json!({ "method": "eth_blockNumber", "chainId": 1 }),
+                json!(session.accounts),
+                json!({ "method": "eth_gasPrice", "chainId": 1 }),
+                json!("0x1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn routes_wallet_and_node_requests() {
+        let bridge = Bridge::new();
+        let (client, session) = connect(&bridge, true);
+        let hash = H256::repeat_byte(0x42);
+        bridge.respond(&session, json!(hash));
+        let (url, node) = http_node(json!("0x2a"));
+        let transport =
+            WalletConnect::with_factory(client, HttpTransportFactory::with_endpoints([(1, url)]))
+                .unwrap();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let transaction = json!({ "from": session.accounts[0], "value": "0x1" });
+        assert_eq!(
+            runtime
+                .block_on(
+                    transport.execute("personal_sendTransaction", vec![transaction, json!("")])
+                )
+                .unwrap(),
+            json!(hash),
+        );
+        assert_eq!(
+            runtime
+                .block_on(transport.execute("eth_blockNumber", vec![]))
+                .unwrap(),
+            json!("0x2a"),
+        );
+
+        let requests = node.try_iter().collect::<Vec<_>>();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["method"], "eth_blockNumber");
+    }
+
+    #[test]
+    fn subscribes_with_the_node() {
+        let (client, session) = connect(&Bridge::new(), true);
+        let node = StandIn::new(|method, _| Ok(json!(method)));
+        let transport = WalletConnect::with_factory(client, node.clone()).unwrap();
+
+        let id = SubscriptionId::from("0x1".to_owned());
+        assert_eq!(
+            block_on(transport.execute("eth_subscribe", vec![json!("newHeads")])).unwrap(),
+            json!("eth_subscribe")
+        );
+        let mut notifications = transport.subscribe(id.clone()).unwrap();
+        assert_eq!(node.subscriptions(), std::slice::from_ref(&id));
+        node.notify(&id, json!({ "number": "0x1" }));
+        assert_eq!(
+            block_on(notifications.next()),
+            Some(json!({ "number": "0x1" }))
+        );
+
+        assert_eq!(
+            block_on(transport.execute("eth_accounts", vec![])).unwrap(),
+            json!(session.accounts)
+        );
+        assert_eq!(node.methods(), ["eth_subscribe"]);
+
+        transport.unsubscribe(id).unwrap();
+        assert!(node.subscriptions().is_empty());
+    }
+}
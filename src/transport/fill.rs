@@ -0,0 +1,237 @@
+//! Filling in missing transaction fields with a node before sending them to
+//! the wallet.
+
+use crate::hex;
+use crate::protocol::Transaction;
+use ethers_core::types::U256;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Map, Value};
+use web3::Transport;
+
+/// A step of the transaction filling pipeline, which fills in a missing
+/// transaction field with data from the node.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Filler {
+    /// Fills in the sender's pending nonce.
+    Nonce,
+    /// Fills in EIP-1559 fees on chains that support them, and the gas price
+    /// otherwise.
+    Fees,
+    /// Fills in the gas limit estimated by the node.
+    GasLimit,
+}
+
+impl Filler {
+    /// All fillers, in the order that they should be applied.
+    pub const ALL: [Filler; 3] = [Filler::Nonce, Filler::Fees, Filler::GasLimit];
+
+    /// Fills in the transaction field for this filler, if it is missing.
+    pub async fn fill<T>(
+        &self,
+        transport: &T,
+        transaction: &mut Transaction,
+    ) -> Result<(), web3::Error>
+    where
+        T: Transport,
+    {
+        match self {
+            Filler::Nonce => {
+                if transaction.nonce.is_none() {
+                    transaction.nonce = Some(
+                        request(
+                            transport,
+                            "eth_getTransactionCount",
+                            vec![json!(transaction.from), json!("pending")],
+                        )
+                        .await?,
+                    );
+                }
+            }
+            Filler::Fees => fill_fees(transport, transaction).await?,
+            Filler::GasLimit => {
+                if transaction.gas_limit.is_none() {
+                    transaction.gas_limit = Some(
+                        request(transport, "eth_estimateGas", vec![estimate(transaction)]).await?,
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Fills in missing transaction fields with the specified fillers, in order.
+pub async fn fill_transaction<T>(
+    transport: &T,
+    fillers: &[Filler],
+    transaction: &mut Transaction,
+) -> Result<(), web3::Error>
+where
+    T: Transport,
+{
+    for filler in fillers {
+        filler.fill(transport, transaction).await?;
+    }
+    Ok(())
+}
+
+async fn fill_fees<T>(transport: &T, transaction: &mut Transaction) -> Result<(), web3::Error>
+where
+    T: Transport,
+{
+    let legacy = transaction.gas_price.is_some()
+        || transaction.transaction_type.map(|kind| kind.as_u64()) == Some(0);
+    if legacy {
+        if transaction.gas_price.is_none() {
+            transaction.gas_price = Some(request(transport, "eth_gasPrice", vec![]).await?);
+        }
+        return Ok(());
+    }
+    if transaction.max_fee_per_gas.is_some() && transaction.max_priority_fee_per_gas.is_some() {
+        return Ok(());
+    }
+
+    let block: Value = request(
+        transport,
+        "eth_getBlockByNumber",
+        vec![json!("latest"), json!(false)],
+    )
+    .await?;
+    let base_fee = match block.get("baseFeePerGas") {
+        Some(base_fee) => serde_json::from_value::<U256>(base_fee.clone())?,
+        // NOTE: The chain does not support EIP-1559, so use a legacy gas
+        // price unless EIP-1559 fees were explicitly requested.
+        None if transaction.max_fee_per_gas.is_none()
+            && transaction.max_priority_fee_per_gas.is_none()
+            && transaction.transaction_type.is_none() =>
+        {
+            transaction.gas_price = Some(request(transport, "eth_gasPrice", vec![]).await?);
+            return Ok(());
+        }
+        None => {
+            return Err(web3::Error::InvalidResponse(
+                "latest block has no base fee for EIP-1559 transaction".to_owned(),
+            ))
+        }
+    };
+
+    let priority_fee = match transaction.max_priority_fee_per_gas {
+        Some(priority_fee) => priority_fee,
+        None => request(transport, "eth_maxPriorityFeePerGas", vec![]).await?,
+    };
+    transaction.max_priority_fee_per_gas = Some(priority_fee);
+    if transaction.max_fee_per_gas.is_none() {
+        // NOTE: Allow the base fee to double before the transaction is priced
+        // out, which covers several full blocks.
+        transaction.max_fee_per_gas = Some(base_fee * 2 + priority_fee);
+    }
+
+    Ok(())
+}
+
+/// Returns the `eth_estimateGas` request for a transaction.
+///
+/// The fee fields are left out, so that the estimate does not depend on the
+/// sender's balance covering the maximum fee.
+fn estimate(transaction: &Transaction) -> Value {
+    let mut request = Map::new();
+    request.insert("from".into(), json!(transaction.from));
+    if let Some(to) = transaction.to {
+        request.insert("to".into(), json!(to));
+    }
+    request.insert("value".into(), json!(transaction.value));
+    request.insert(
+        "data".into(),
+        json!(format!("0x{}", hex::encode(&transaction.data))),
+    );
+    if let Some(access_list) = &transaction.access_list {
+        request.insert("accessList".into(), json!(access_list));
+    }
+    Value::Object(request)
+}
+
+async fn request<T, R>(transport: &T, method: &str, params: Vec<Value>) -> Result<R, web3::Error>
+where
+    T: Transport,
+    R: DeserializeOwned,
+{
+    let result = transport.execute(method, params).await?;
+    Ok(serde_json::from_value(result)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::tests::StandIn;
+    use ethers_core::types::Address;
+    use futures::executor::block_on;
+
+    /// A stand-in node that serves the requests used for filling
+    /// transactions.
+    fn stand_in(base_fee: Option<U256>) -> StandIn {
+        StandIn::new(move |method, _| match method {
+            "eth_getTransactionCount" => Ok(json!("0x7")),
+            "eth_getBlockByNumber" => Ok(match base_fee {
+                Some(base_fee) => json!({ "baseFeePerGas": base_fee }),
+                None => json!({}),
+            }),
+            "eth_maxPriorityFeePerGas" => Ok(json!("0x2")),
+            "eth_gasPrice" => Ok(json!("0x64")),
+            "eth_estimateGas" => Ok(json!("0x5208")),
+            _ => Err(web3::Error::Unreachable),
+        })
+    }
+
+    #[test]
+    fn fills_eip1559_transactions() {
+        let node = stand_in(Some(10.into()));
+        let mut transaction = Transaction {
+            from: Address::repeat_byte(1),
+            ..Default::default()
+        };
+        block_on(fill_transaction(&node, &Filler::ALL, &mut transaction)).unwrap();
+
+        assert_eq!(transaction.nonce, Some(7.into()));
+        assert_eq!(transaction.max_priority_fee_per_gas, Some(2.into()));
+        assert_eq!(transaction.max_fee_per_gas, Some(22.into()));
+        assert_eq!(transaction.gas_price, None);
+        assert_eq!(transaction.gas_limit, Some(21_000.into()));
+
+        assert_eq!(
+            node.params("eth_estimateGas")[0][0],
+            json!({
+                "from": Address::repeat_byte(1),
+                "value": "0x0",
+                "data": "0x",
+            })
+        );
+    }
+
+    #[test]
+    fn fills_legacy_gas_price() {
+        let node = stand_in(None);
+        let mut transaction = Transaction::default();
+        block_on(Filler::Fees.fill(&node, &mut transaction)).unwrap();
+        assert_eq!(transaction.gas_price, Some(100.into()));
+        assert_eq!(transaction.max_fee_per_gas, None);
+    }
+
+    #[test]
+    fn keeps_existing_fields() {
+        let node = stand_in(None);
+        let mut transaction = Transaction {
+            nonce: Some(1.into()),
+            gas_limit: Some(50_000.into()),
+            max_fee_per_gas: Some(3.into()),
+            max_priority_fee_per_gas: Some(1.into()),
+            ..Default::default()
+        };
+        block_on(fill_transaction(&node, &Filler::ALL, &mut transaction)).unwrap();
+
+        assert_eq!(transaction.nonce, Some(1.into()));
+        assert_eq!(transaction.gas_limit, Some(50_000.into()));
+        assert_eq!(transaction.max_fee_per_gas, Some(3.into()));
+        assert!(node.methods().is_empty());
+    }
+}
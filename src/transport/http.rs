@@ -0,0 +1,162 @@
+use super::TransportFactory;
+use std::collections::HashMap;
+use thiserror::Error;
+use web3::transports::Http;
+
+/// Public RPC endpoints for commonly used networks.
+const DEFAULT_ENDPOINTS: &[(u64, &str)] = &[
+    (1, "https://ethereum-rpc.publicnode.com"),
+    (10, "https://mainnet.optimism.io"),
+    (100, "https://rpc.gnosischain.com"),
+    (137, "https://polygon-rpc.com"),
+    (8453, "https://mainnet.base.org"),
+    (42161, "https://arb1.arbitrum.io/rpc"),
+    (11155111, "https://ethereum-sepolia-rpc.publicnode.com"),
+];
+
+/// Infura network names for supported chain IDs.
+const INFURA_NETWORKS: &[(u64, &str)] = &[
+    (1, "mainnet"),
+    (10, "optimism-mainnet"),
+    (137, "polygon-mainnet"),
+    (8453, "base-mainnet"),
+    (42161, "arbitrum-mainnet"),
+    (11155111, "sepolia"),
+];
+
+/// The placeholder in URL templates that gets replaced with the chain ID.
+pub const CHAIN_ID_PLACEHOLDER: &str = "{chain_id}";
+
+/// A transport factory that creates HTTP transports for RPC endpoints
+/// configured by chain ID.
+///
+/// Chains without an explicitly configured endpoint use the factory's URL
+/// template, if any, where `{chain_id}` is replaced with the chain ID.
+#[derive(Clone, Debug)]
+pub struct HttpTransportFactory {
+    endpoints: HashMap<u64, String>,
+    template: Option<String>,
+}
+
+impl Default for HttpTransportFactory {
+    fn default() -> Self {
+        HttpTransportFactory::with_endpoints(
+            DEFAULT_ENDPOINTS
+                .iter()
+                .map(|(chain_id, url)| (*chain_id, url.to_string())),
+        )
+    }
+}
+
+impl HttpTransportFactory {
+    /// Creates a new factory without any configured endpoints.
+    pub fn empty() -> Self {
+        HttpTransportFactory {
+            endpoints: HashMap::new(),
+            template: None,
+        }
+    }
+
+    /// Creates a new factory with the specified endpoints.
+    pub fn with_endpoints(endpoints: impl IntoIterator<Item = (u64, String)>) -> Self {
+        HttpTransportFactory {
+            endpoints: endpoints.into_iter().collect(),
+            template: None,
+        }
+    }
+
+    /// Creates a new factory that uses a provider URL template for all chains,
+    /// for example `https://rpc.provider.invalid/{chain_id}/KEY`.
+    pub fn with_template(template: impl Into<String>) -> Self {
+        HttpTransportFactory {
+            template: Some(template.into()),
+            ..HttpTransportFactory::empty()
+        }
+    }
+
+    /// Creates a new factory with Infura endpoints for the specified project.
+    pub fn infura(project_id: &str) -> Self {
+        HttpTransportFactory::with_endpoints(INFURA_NETWORKS.iter().map(|(chain_id, network)| {
+            (
+                *chain_id,
+                format!("https://{}.infura.io/v3/{}", network, project_id),
+            )
+        }))
+    }
+
+    /// Sets the endpoint for a chain, replacing any existing one.
+    pub fn endpoint(mut self, chain_id: u64, url: impl Into<String>) -> Self {
+        self.endpoints.insert(chain_id, url.into());
+        self
+    }
+
+    /// Sets the URL template for chains without a configured endpoint.
+    pub fn template(mut self, template: impl Into<String>) -> Self {
+        self.template = Some(template.into());
+        self
+    }
+
+    /// Returns the endpoint URL for the specified chain.
+    pub fn url(&self, chain_id: u64) -> Option<String> {
+        self.endpoints.get(&chain_id).cloned().or_else(|| {
+            self.template
+                .as_ref()
+                .map(|template| template.replace(CHAIN_ID_PLACEHOLDER, &chain_id.to_string()))
+        })
+    }
+}
+
+impl TransportFactory for HttpTransportFactory {
+    type Transport = Http;
+    type Error = HttpTransportError;
+
+    fn new(&mut self, chain_id: u64) -> Result<Self::Transport, Self::Error> {
+        let url = self
+            .url(chain_id)
+            .ok_or(HttpTransportError::UnknownChain(chain_id))?;
+        Ok(Http::new(&url)?)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum HttpTransportError {
+    #[error("no RPC endpoint configured for chain ID {0}")]
+    UnknownChain(u64),
+    #[error("error creating HTTP transport: {0}")]
+    Http(#[from] web3::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoint_urls() {
+        let factory = HttpTransportFactory::default();
+        assert_eq!(factory.url(100).unwrap(), "https://rpc.gnosischain.com");
+        assert_eq!(factory.url(31337), None);
+
+        let factory = factory
+            .endpoint(1, "http://localhost:8545")
+            .template("https://rpc.provider.invalid/{chain_id}/key");
+        assert_eq!(factory.url(1).unwrap(), "http://localhost:8545");
+        assert_eq!(
+            factory.url(31337).unwrap(),
+            "https://rpc.provider.invalid/31337/key"
+        );
+
+        let factory = HttpTransportFactory::infura("project");
+        assert_eq!(
+            factory.url(11155111).unwrap(),
+            "https://sepolia.infura.io/v3/project"
+        );
+    }
+
+    #[test]
+    fn unknown_chains() {
+        assert!(matches!(
+            HttpTransportFactory::empty().new(1),
+            Err(HttpTransportError::UnknownChain(1))
+        ));
+    }
+}
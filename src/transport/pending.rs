@@ -0,0 +1,359 @@
+//! Waiting for transactions sent by the wallet to be mined.
+
+use ethers_core::types::{Address, TransactionReceipt, H256, U256, U64};
+use futures::future::{self, Either};
+use futures_timer::Delay;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::time::Duration;
+use thiserror::Error;
+use web3::Transport;
+
+/// The default interval for polling the node for a transaction receipt.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(7);
+
+/// The number of consecutive polls that a previously seen transaction must be
+/// missing from the node for it to be considered dropped.
+const DROPPED_AFTER_MISSES: u32 = 3;
+
+/// A transaction that was sent by the wallet and is waiting to be mined.
+#[derive(Clone, Debug)]
+pub struct PendingTransaction<T> {
+    transport: T,
+    hash: H256,
+    from: Address,
+    nonce: Option<U256>,
+    confirmations: u64,
+    interval: Duration,
+    timeout: Option<Duration>,
+}
+
+impl<T> PendingTransaction<T>
+where
+    T: Transport,
+{
+    /// Creates a new pending transaction that polls the specified node
+    /// transport for its receipt.
+    pub fn new(transport: T, hash: H256, from: Address) -> Self {
+        PendingTransaction {
+            transport,
+            hash,
+            from,
+            nonce: None,
+            confirmations: 1,
+            interval: DEFAULT_POLL_INTERVAL,
+            timeout: None,
+        }
+    }
+
+    pub fn hash(&self) -> H256 {
+        self.hash
+    }
+
+    /// Sets the nonce of the transaction, which is used to detect when it was
+    /// replaced. If it is not set, it is read from the node once the
+    /// transaction is seen.
+    pub fn nonce(mut self, nonce: U256) -> Self {
+        self.nonce = Some(nonce);
+        self
+    }
+
+    /// Sets the number of blocks, including the one that the transaction was
+    /// mined in, to wait for.
+    pub fn confirmations(mut self, confirmations: u64) -> Self {
+        self.confirmations = confirmations;
+        self
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the maximum duration to wait for the transaction.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Waits for the transaction to be mined with the configured number of
+    /// confirmations, returning its receipt.
+    pub async fn wait(mut self) -> Result<TransactionReceipt, PendingTransactionError> {
+        let timeout = self.timeout;
+        let poll = Box::pin(self.poll());
+        match timeout {
+            Some(timeout) => match future::select(poll, Delay::new(timeout)).await {
+                Either::Left((result, _)) => result,
+                Either::Right(_) => Err(PendingTransactionError::Timeout),
+            },
+            None => poll.await,
+        }
+    }
+
+    async fn poll(&mut self) -> Result<TransactionReceipt, PendingTransactionError> {
+        let mut misses = None;
+        loop {
+            // NOTE: Only check whether the transaction was replaced or dropped
+            // while it is not mined, as the sender's nonce advances as soon as
+            // it is, long before it has enough confirmations.
+            let receipt = match self.receipt().await? {
+                Some(receipt) => Some(receipt),
+                None => self.check_pending(&mut misses).await?,
+            };
+            if let Some(receipt) = receipt {
+                if self.is_confirmed(&receipt).await? {
+                    return Ok(receipt);
+                }
+            }
+
+            Delay::new(self.interval).await;
+        }
+    }
+
+    /// Checks whether a transaction that is not mined was replaced or
+    /// dropped, returning its receipt if it was mined in the meantime.
+    async fn check_pending(
+        &mut self,
+        misses: &mut Option<u32>,
+    ) -> Result<Option<TransactionReceipt>, PendingTransactionError> {
+        // NOTE: Transactions are only considered dropped once the node stops
+        // knowing about them for several polls in a row, as the wallet may
+        // send transactions through a different node and they can take time
+        // to propagate, and load balanced nodes may briefly disagree.
+        let transaction: Option<Value> = self
+            .request("eth_getTransactionByHash", vec![json!(self.hash)])
+            .await?;
+        match (&transaction, misses.as_mut()) {
+            (Some(_), _) => *misses = Some(0),
+            (None, Some(misses)) => *misses += 1,
+            (None, None) => {}
+        }
+        if let Some(transaction) = &transaction {
+            if self.nonce.is_none() {
+                self.nonce = Some(
+                    serde_json::from_value(transaction["nonce"].clone())
+                        .map_err(web3::Error::from)?,
+                );
+            }
+        }
+
+        if let Some(nonce) = self.nonce {
+            let next_nonce: U256 = self
+                .request(
+                    "eth_getTransactionCount",
+                    vec![json!(self.from), json!("latest")],
+                )
+                .await?;
+            if next_nonce > nonce {
+                // NOTE: Check for the receipt again, in case the transaction
+                // was mined since it was last checked.
+                return match self.receipt().await? {
+                    Some(receipt) => Ok(Some(receipt)),
+                    None => Err(PendingTransactionError::Replaced(nonce)),
+                };
+            }
+            if misses.is_some_and(|misses| misses >= DROPPED_AFTER_MISSES) {
+                return Err(PendingTransactionError::Dropped);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the transaction receipt if the transaction was mined.
+    async fn receipt(&self) -> Result<Option<TransactionReceipt>, PendingTransactionError> {
+        let receipt: Option<TransactionReceipt> = self
+            .request("eth_getTransactionReceipt", vec![json!(self.hash)])
+            .await?;
+        Ok(receipt.filter(|receipt| receipt.block_number.is_some()))
+    }
+
+    /// Returns whether a mined transaction has the configured number of
+    /// confirmations.
+    async fn is_confirmed(
+        &self,
+        receipt: &TransactionReceipt,
+    ) -> Result<bool, PendingTransactionError> {
+        let block_number = match receipt.block_number {
+            Some(block_number) => block_number,
+            None => return Ok(false),
+        };
+        if self.confirmations <= 1 {
+            return Ok(true);
+        }
+
+        let current: U64 = self.request("eth_blockNumber", vec![]).await?;
+        Ok(current + 1 >= block_number + self.confirmations)
+    }
+
+    async fn request<R>(&self, method: &str, params: Vec<Value>) -> Result<R, web3::Error>
+    where
+        R: DeserializeOwned,
+    {
+        let result = self.transport.execute(method, params).await?;
+        Ok(serde_json::from_value(result)?)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PendingTransactionError {
+    #[error("node error: {0}")]
+    Node(#[from] web3::Error),
+    #[error("transaction was replaced by another transaction with nonce {0}")]
+    Replaced(U256),
+    #[error("transaction was dropped")]
+    Dropped,
+    #[error("timed out waiting for transaction")]
+    Timeout,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::tests::StandIn;
+    use futures::executor::block_on;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// Scripted node responses for each poll, where the last response is
+    /// repeated once they run out.
+    #[derive(Default)]
+    struct Script {
+        receipts: Vec<Value>,
+        transactions: Vec<Value>,
+        block_numbers: Vec<u64>,
+        nonce: u64,
+    }
+
+    impl Script {
+        fn stand_in(self) -> StandIn {
+            let polls = Mutex::new(HashMap::<String, usize>::new());
+            let block_numbers = self
+                .block_numbers
+                .iter()
+                .map(|block_number| json!(U64::from(*block_number)))
+                .collect::<Vec<_>>();
+            StandIn::new(move |method, _| {
+                let scripted = |responses: &[Value]| {
+                    let mut polls = polls.lock().unwrap();
+                    let count = polls.entry(method.to_owned()).or_default();
+                    let response = responses[(*count).min(responses.len() - 1)].clone();
+                    *count += 1;
+                    response
+                };
+                match method {
+                    "eth_getTransactionReceipt" => Ok(scripted(&self.receipts)),
+                    "eth_getTransactionByHash" => Ok(scripted(&self.transactions)),
+                    "eth_blockNumber" => Ok(scripted(&block_numbers)),
+                    "eth_getTransactionCount" => Ok(json!(U256::from(self.nonce))),
+                    _ => Err(web3::Error::Unreachable),
+                }
+            })
+        }
+    }
+
+    fn receipt(block_number: u64) -> Value {
+        json!({
+            "transactionHash": H256::repeat_byte(1),
+            "transactionIndex": "0x0",
+            "blockHash": H256::repeat_byte(2),
+            "blockNumber": U64::from(block_number),
+            "from": Address::repeat_byte(3),
+            "to": Address::repeat_byte(4),
+            "cumulativeGasUsed": "0x5208",
+            "gasUsed": "0x5208",
+            "logs": [],
+            "logsBloom": format!("0x{}", "00".repeat(256)),
+            "status": "0x1",
+        })
+    }
+
+    fn pending(node: &StandIn) -> PendingTransaction<StandIn> {
+        PendingTransaction::new(node.clone(), H256::repeat_byte(1), Address::repeat_byte(3))
+            .interval(Duration::from_millis(1))
+    }
+
+    #[test]
+    fn waits_for_confirmations() {
+        // NOTE: The sender's nonce already advanced once the transaction is
+        // mined, which must not be mistaken for a replacement.
+        let node = Script {
+            receipts: vec![Value::Null, receipt(10)],
+            transactions: vec![json!({ "nonce": "0x5" })],
+            block_numbers: vec![10, 11, 12],
+            nonce: 6,
+        }
+        .stand_in();
+        let receipt = block_on(pending(&node).confirmations(3).wait()).unwrap();
+
+        assert_eq!(receipt.block_number, Some(10.into()));
+        assert_eq!(node.params("eth_blockNumber").len(), 3);
+    }
+
+    #[test]
+    fn detects_replaced_transactions() {
+        let node = Script {
+            receipts: vec![Value::Null],
+            transactions: vec![Value::Null],
+            nonce: 6,
+            ..Default::default()
+        }
+        .stand_in();
+        assert!(matches!(
+            block_on(pending(&node).nonce(5.into()).wait()),
+            Err(PendingTransactionError::Replaced(nonce)) if nonce == 5.into()
+        ));
+    }
+
+    #[test]
+    fn detects_dropped_transactions() {
+        let node = Script {
+            receipts: vec![Value::Null],
+            transactions: vec![json!({ "nonce": "0x5" }), Value::Null],
+            nonce: 5,
+            ..Default::default()
+        }
+        .stand_in();
+        assert!(matches!(
+            block_on(pending(&node).wait()),
+            Err(PendingTransactionError::Dropped)
+        ));
+        assert_eq!(
+            node.params("eth_getTransactionByHash").len(),
+            1 + DROPPED_AFTER_MISSES as usize
+        );
+    }
+
+    #[test]
+    fn tolerates_missing_transactions() {
+        let node = Script {
+            receipts: vec![Value::Null, Value::Null, Value::Null, receipt(10)],
+            transactions: vec![
+                json!({ "nonce": "0x5" }),
+                Value::Null,
+                json!({ "nonce": "0x5" }),
+            ],
+            nonce: 5,
+            ..Default::default()
+        }
+        .stand_in();
+        let receipt = block_on(pending(&node).wait()).unwrap();
+
+        assert_eq!(receipt.block_number, Some(10.into()));
+        assert_eq!(node.params("eth_getTransactionByHash").len(), 3);
+    }
+
+    #[test]
+    fn times_out() {
+        let node = Script {
+            receipts: vec![Value::Null],
+            transactions: vec![Value::Null],
+            ..Default::default()
+        }
+        .stand_in();
+        assert!(matches!(
+            block_on(pending(&node).timeout(Duration::from_millis(20)).wait()),
+            Err(PendingTransactionError::Timeout)
+        ));
+    }
+}
@@ -0,0 +1,394 @@
+//! An EIP-1193 style provider for code that expects a `request` interface and
+//! provider events.
+
+use crate::client::{
+    is_wallet_method, wallet_method, CallError, Client, SessionError, SessionState,
+};
+use crate::errors::RpcErrorKind;
+use crate::uri::Uri;
+use ethers_core::types::{Address, U64};
+use futures::future;
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+use thiserror::Error;
+use web3::Transport;
+
+/// A provider that handles account and signing requests with the wallet
+/// connected to a WalletConnect session, and sends all other requests to a
+/// read transport.
+#[derive(Clone)]
+pub struct Provider<T> {
+    client: Arc<Client>,
+    transport: T,
+    pairing: Arc<dyn Fn(Uri) + Send + Sync>,
+}
+
+impl<T> Debug for Provider<T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Provider")
+            .field("client", &self.client)
+            .field("transport", &self.transport)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The arguments of an EIP-1193 request.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RequestArguments {
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<Value>,
+}
+
+impl RequestArguments {
+    pub fn new(method: impl Into<String>, params: Vec<Value>) -> Self {
+        RequestArguments {
+            method: method.into(),
+            params: Some(Value::Array(params)),
+        }
+    }
+}
+
+impl<T> Provider<T>
+where
+    T: Transport,
+{
+    /// Creates a new provider. The pairing function is called with the URI to
+    /// display to the user when `eth_requestAccounts` needs to pair a new
+    /// session.
+    pub fn new<F>(client: impl Into<Arc<Client>>, transport: T, pairing: F) -> Self
+    where
+        F: Fn(Uri) + Send + Sync + 'static,
+    {
+        Provider {
+            client: client.into(),
+            transport,
+            pairing: Arc::new(pairing),
+        }
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Returns the transport used for read requests.
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Sends a request to the wallet or the read transport.
+    pub async fn request(&self, args: RequestArguments) -> Result<Value, ProviderRpcError> {
+        let params = match args.params {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Array(params)) => params,
+            Some(_) => {
+                return Err(ProviderRpcError::new(
+                    RpcErrorKind::InvalidParams,
+                    "expected positional parameters",
+                ))
+            }
+        };
+
+        match wallet_method(&args.method) {
+            "eth_requestAccounts" => {
+                let pairing = self.pairing.clone();
+                let (accounts, _) = self.client.ensure_session(move |uri| pairing(uri)).await?;
+                if accounts.is_empty() {
+                    return Err(ProviderRpcError::new(
+                        RpcErrorKind::UserRejected,
+                        "user rejected the session request",
+                    ));
+                }
+                Ok(json!(accounts))
+            }
+            // NOTE: EIP-1193 providers return no accounts instead of an error
+            // when they are disconnected.
+            "eth_accounts" => {
+                let (accounts, _) = self.client.accounts().unwrap_or_default();
+                Ok(json!(accounts))
+            }
+            "eth_chainId" => match self.client.accounts() {
+                Ok((_, chain_id)) => Ok(json!(U64::from(chain_id))),
+                Err(_) => Ok(self.transport.execute("eth_chainId", params).await?),
+            },
+            method if is_wallet_method(method) => {
+                Ok(self.client.wallet_request(method, params).await?)
+            }
+            method => Ok(self.transport.execute(method, params).await?),
+        }
+    }
+
+    /// Returns a stream of provider events for changes to the session.
+    pub fn events(&self) -> BoxStream<'static, ProviderEvent> {
+        self.client
+            .session_updates()
+            .scan(None, |last: &mut Option<SessionState>, state| {
+                let events = match last.replace(state.clone()) {
+                    Some(last) => changes(&last, &state),
+                    None => Vec::new(),
+                };
+                future::ready(Some(stream::iter(events)))
+            })
+            .flatten()
+            .boxed()
+    }
+}
+
+/// Returns the provider events for a change in session state.
+fn changes(last: &SessionState, state: &SessionState) -> Vec<ProviderEvent> {
+    match (last.connected, state.connected) {
+        (false, true) => vec![
+            ProviderEvent::Connect {
+                chain_id: state.chain_id,
+            },
+            ProviderEvent::AccountsChanged(state.accounts.clone()),
+        ],
+        (true, false) => vec![
+            ProviderEvent::AccountsChanged(Vec::new()),
+            ProviderEvent::Disconnect(ProviderRpcError::new(
+                RpcErrorKind::Disconnected,
+                "session disconnected",
+            )),
+        ],
+        (true, true) => {
+            let mut events = Vec::new();
+            if last.chain_id != state.chain_id {
+                events.push(ProviderEvent::ChainChanged(state.chain_id));
+            }
+            if last.accounts != state.accounts {
+                events.push(ProviderEvent::AccountsChanged(state.accounts.clone()));
+            }
+            events
+        }
+        (false, false) => Vec::new(),
+    }
+}
+
+/// An EIP-1193 provider event.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProviderEvent {
+    Connect { chain_id: u64 },
+    Disconnect(ProviderRpcError),
+    ChainChanged(u64),
+    AccountsChanged(Vec<Address>),
+}
+
+impl ProviderEvent {
+    /// Returns the EIP-1193 event name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProviderEvent::Connect { .. } => "connect",
+            ProviderEvent::Disconnect(_) => "disconnect",
+            ProviderEvent::ChainChanged(_) => "chainChanged",
+            ProviderEvent::AccountsChanged(_) => "accountsChanged",
+        }
+    }
+
+    /// Returns the event data that is passed to EIP-1193 event listeners.
+    pub fn data(&self) -> Value {
+        match self {
+            ProviderEvent::Connect { chain_id } => json!({ "chainId": U64::from(*chain_id) }),
+            ProviderEvent::Disconnect(err) => json!(err),
+            ProviderEvent::ChainChanged(chain_id) => json!(U64::from(*chain_id)),
+            ProviderEvent::AccountsChanged(accounts) => json!(accounts),
+        }
+    }
+}
+
+/// An EIP-1193 provider error.
+#[derive(Clone, Debug, Error, PartialEq, Serialize)]
+#[error("{message} ({code})")]
+pub struct ProviderRpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl ProviderRpcError {
+    pub fn new(kind: RpcErrorKind, message: impl Into<String>) -> Self {
+        ProviderRpcError {
+            code: kind.code(),
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    pub fn kind(&self) -> RpcErrorKind {
+        RpcErrorKind::from_code(self.code)
+    }
+}
+
+impl From<jsonrpc_core::Error> for ProviderRpcError {
+    fn from(err: jsonrpc_core::Error) -> Self {
+        ProviderRpcError {
+            code: err.code.code(),
+            message: err.message,
+            data: err.data,
+        }
+    }
+}
+
+impl From<CallError> for ProviderRpcError {
+    fn from(err: CallError) -> Self {
+        match err {
+            CallError::Rpc(err) => err.into(),
+            CallError::Json(err) => {
+                ProviderRpcError::new(RpcErrorKind::InternalError, err.to_string())
+            }
+            err => ProviderRpcError::new(RpcErrorKind::Disconnected, err.to_string()),
+        }
+    }
+}
+
+impl From<SessionError> for ProviderRpcError {
+    fn from(err: SessionError) -> Self {
+        match err {
+            SessionError::Call(err) => err.into(),
+            SessionError::Pending => {
+                ProviderRpcError::new(RpcErrorKind::ResourceUnavailable, err.to_string())
+            }
+            err => ProviderRpcError::new(RpcErrorKind::InternalError, err.to_string()),
+        }
+    }
+}
+
+impl From<web3::Error> for ProviderRpcError {
+    fn from(err: web3::Error) -> Self {
+        match err {
+            web3::Error::Rpc(err) => err.into(),
+            web3::Error::Unreachable | web3::Error::Transport(_) => {
+                ProviderRpcError::new(RpcErrorKind::ChainDisconnected, err.to_string())
+            }
+            err => ProviderRpcError::new(RpcErrorKind::InternalError, err.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{connect, Bridge};
+    use crate::protocol::Topic;
+    use crate::transport::tests::StandIn;
+    use ethers_core::types::H256;
+    use futures::executor::block_on;
+    use std::sync::Mutex;
+
+    /// Returns a stand-in node that answers requests with their method.
+    fn node() -> StandIn {
+        StandIn::new(|method, _| Ok(json!(method)))
+    }
+
+    fn request(provider: &Provider<StandIn>, method: &str, params: Vec<Value>) -> Value {
+        block_on(provider.request(RequestArguments::new(method, params))).unwrap()
+    }
+
+    fn connected(accounts: Vec<Address>, chain_id: u64) -> SessionState {
+        SessionState {
+            connected: true,
+            accounts,
+            chain_id,
+        }
+    }
+
+    #[test]
+    fn session_changes() {
+        let account = Address::repeat_byte(1);
+        let disconnected = SessionState::default();
+
+        assert_eq!(
+            changes(&disconnected, &connected(vec![account], 1)),
+            vec![
+                ProviderEvent::Connect { chain_id: 1 },
+                ProviderEvent::AccountsChanged(vec![account]),
+            ]
+        );
+        assert_eq!(
+            changes(&connected(vec![account], 1), &connected(vec![], 100)),
+            vec![
+                ProviderEvent::ChainChanged(100),
+                ProviderEvent::AccountsChanged(vec![]),
+            ]
+        );
+        assert!(changes(&connected(vec![account], 1), &connected(vec![account], 1)).is_empty());
+
+        let events = changes(&connected(vec![account], 1), &disconnected);
+        assert_eq!(events[0], ProviderEvent::AccountsChanged(vec![]));
+        assert_eq!(events[1].name(), "disconnect");
+        assert_eq!(events[1].data()["code"], 4900);
+    }
+
+    #[test]
+    fn event_data() {
+        assert_eq!(
+            ProviderEvent::Connect { chain_id: 100 }.data(),
+            json!({ "chainId": "0x64" })
+        );
+        assert_eq!(ProviderEvent::ChainChanged(1).data(), json!("0x1"));
+    }
+
+    #[test]
+    fn requests_accounts_by_pairing() {
+        let bridge = Bridge::new();
+        let (client, session) = connect(&bridge, false);
+        let accounts = vec![Address::repeat_byte(2)];
+        bridge.respond(
+            &session,
+            json!({
+                "approved": true,
+                "accounts": accounts,
+                "chainId": 1,
+                "peerId": Topic::new(),
+                "peerMeta": {
+                    "description": "",
+                    "url": "https://wallet.invalid",
+                    "icons": [],
+                    "name": "wallet",
+                },
+            }),
+        );
+        let uris = Arc::new(Mutex::new(Vec::new()));
+        let provider = Provider::new(client, node(), {
+            let uris = uris.clone();
+            move |uri| uris.lock().unwrap().push(uri)
+        });
+
+        assert_eq!(request(&provider, "eth_accounts", vec![]), json!([]));
+        assert!(uris.lock().unwrap().is_empty());
+
+        assert_eq!(
+            request(&provider, "eth_requestAccounts", vec![]),
+            json!(accounts)
+        );
+        assert_eq!(*uris.lock().unwrap(), [session.uri()]);
+        assert_eq!(request(&provider, "eth_accounts", vec![]), json!(accounts));
+    }
+
+    #[test]
+    fn routes_wallet_and_node_requests() {
+        let bridge = Bridge::new();
+        let (client, session) = connect(&bridge, true);
+        let hash = H256::repeat_byte(0x42);
+        bridge.respond(&session, json!(hash));
+        let node = node();
+        let provider = Provider::new(client, node.clone(), |_| panic!("unexpected pairing"));
+
+        let transaction = json!({ "from": session.accounts[0], "value": "0x1" });
+        assert_eq!(
+            request(&provider, "eth_sendTransaction", vec![transaction]),
+            json!(hash)
+        );
+        assert_eq!(
+            request(&provider, "eth_blockNumber", vec![]),
+            json!("eth_blockNumber")
+        );
+        assert_eq!(node.methods(), ["eth_blockNumber"]);
+    }
+}
@@ -0,0 +1,408 @@
+//! A local JSON RPC server that exposes the wallet connected to a
+//! WalletConnect session to tools that expect a node RPC endpoint.
+
+use hyper::body::HttpBody;
+use hyper::header::{HeaderMap, HeaderValue, CONTENT_TYPE, ORIGIN};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Server, StatusCode};
+use jsonrpc_core::{Call, Id, MethodCall, Output, Request, Response, Version};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use web3::{BatchTransport, RequestId};
+
+/// The maximum size of HTTP request bodies, in bytes.
+const MAX_REQUEST_SIZE: usize = 5 * 1024 * 1024;
+
+/// A JSON RPC proxy that handles requests with a transport.
+///
+/// When used with a [`WalletConnect`](super::WalletConnect) transport, account
+/// and signing requests are handled by the wallet and all other requests are
+/// forwarded to the node for the session's current chain.
+#[derive(Clone, Debug)]
+pub struct Proxy<T> {
+    transport: T,
+}
+
+/// A call of a JSON RPC request that is either sent to the transport, along
+/// with the ID to respond with, or immediately answered with an error.
+enum Prepared {
+    Send(Option<Id>, Option<Version>, Call),
+    Invalid(Output),
+}
+
+impl<T> Proxy<T>
+where
+    T: BatchTransport,
+{
+    pub fn new(transport: T) -> Self {
+        Proxy { transport }
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Handles a JSON RPC request, returning `None` if it only contains
+    /// notifications.
+    pub async fn handle(&self, request: Request) -> Option<Response> {
+        match request {
+            Request::Single(call) => {
+                let output = match prepare(0, call) {
+                    Prepared::Send(id, jsonrpc, call) => {
+                        let result = self.transport.send(0, call).await.map_err(rpc_error);
+                        Output::from(result, id?, jsonrpc)
+                    }
+                    Prepared::Invalid(output) => output,
+                };
+                Some(Response::Single(output))
+            }
+            // NOTE: Empty batches are invalid requests, while batches of only
+            // notifications are answered with no response at all.
+            Request::Batch(calls) if calls.is_empty() => Some(Response::Single(Output::from(
+                Err(jsonrpc_core::Error::invalid_request()),
+                Id::Null,
+                Some(Version::V2),
+            ))),
+            Request::Batch(calls) => {
+                let prepared = calls
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, call)| prepare(index, call))
+                    .collect::<Vec<_>>();
+
+                // NOTE: Requests are sent with their index as ID, since node
+                // transports match batch responses by numeric ID.
+                let requests = prepared
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, prepared)| match prepared {
+                        Prepared::Send(_, _, call) => Some((index as RequestId, call.clone())),
+                        Prepared::Invalid(_) => None,
+                    })
+                    .collect::<Vec<_>>();
+                let sent = requests.len();
+                let results = if requests.is_empty() {
+                    Vec::new()
+                } else {
+                    match self.transport.send_batch(requests).await {
+                        Ok(results) => results
+                            .into_iter()
+                            .map(|result| result.map_err(rpc_error))
+                            .collect(),
+                        Err(err) => vec![Err(rpc_error(err)); sent],
+                    }
+                };
+                let mut results = results.into_iter();
+
+                let outputs = prepared
+                    .into_iter()
+                    .filter_map(|prepared| match prepared {
+                        Prepared::Send(id, jsonrpc, _) => {
+                            let result = results.next().unwrap_or_else(|| {
+                                Err(rpc_error(web3::Error::InvalidResponse(
+                                    "missing batch response".to_owned(),
+                                )))
+                            });
+                            Some(Output::from(result, id?, jsonrpc))
+                        }
+                        Prepared::Invalid(output) => Some(output),
+                    })
+                    .collect::<Vec<_>>();
+                if outputs.is_empty() {
+                    return None;
+                }
+                Some(Response::Batch(outputs))
+            }
+        }
+    }
+
+    /// Handles an HTTP JSON RPC request.
+    async fn respond(&self, request: hyper::Request<Body>) -> hyper::Response<Body> {
+        if request.method() != Method::POST {
+            return status(StatusCode::METHOD_NOT_ALLOWED);
+        }
+        // NOTE: Browsers send simple cross-origin POST requests to local
+        // servers without a CORS preflight, so requests from web pages are
+        // rejected to keep arbitrary websites from using the wallet.
+        if request.headers().contains_key(ORIGIN) {
+            return status(StatusCode::FORBIDDEN);
+        }
+        if !is_json(request.headers()) {
+            return status(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        }
+        let body = match read_body(request.into_body()).await {
+            Ok(Some(body)) => body,
+            Ok(None) => return status(StatusCode::PAYLOAD_TOO_LARGE),
+            Err(_) => return status(StatusCode::BAD_REQUEST),
+        };
+
+        let response = match serde_json::from_slice::<Request>(&body) {
+            Ok(request) => match self.handle(request).await {
+                Some(response) => response,
+                None => return status(StatusCode::NO_CONTENT),
+            },
+            Err(_) => Response::from(jsonrpc_core::Error::parse_error(), Some(Version::V2)),
+        };
+        let body = serde_json::to_vec(&response).expect("JSON RPC responses always serialize");
+
+        let mut response = hyper::Response::new(Body::from(body));
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        response
+    }
+}
+
+impl<T> Proxy<T>
+where
+    T: BatchTransport + Send + Sync + 'static,
+    T::Out: Send,
+    T::Batch: Send,
+{
+    /// Serves JSON RPC requests over HTTP on the specified address, for
+    /// example `127.0.0.1:8545`.
+    ///
+    /// Only `application/json` POST requests of up to 5 MiB are accepted.
+    /// Requests with an `Origin` header are rejected, so web pages can't use
+    /// the proxy.
+    pub async fn serve(self, addr: &SocketAddr) -> Result<(), hyper::Error> {
+        let proxy = Arc::new(self);
+        let make_service = make_service_fn(move |_| {
+            let proxy = proxy.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let proxy = proxy.clone();
+                    async move { Ok::<_, Infallible>(proxy.respond(request).await) }
+                }))
+            }
+        });
+
+        log::info!("serving JSON RPC on http://{}", addr);
+        Server::try_bind(addr)?.serve(make_service).await
+    }
+}
+
+/// Prepares a call to be sent with the specified numeric ID.
+///
+/// Notifications are sent as method calls, but have no ID to respond with.
+fn prepare(index: usize, call: Call) -> Prepared {
+    let (id, mut method_call) = match call {
+        Call::MethodCall(method_call) => (Some(method_call.id.clone()), method_call),
+        Call::Notification(notification) => (
+            None,
+            MethodCall {
+                jsonrpc: notification.jsonrpc,
+                method: notification.method,
+                params: notification.params,
+                id: Id::Null,
+            },
+        ),
+        Call::Invalid { id } => {
+            return Prepared::Invalid(Output::from(
+                Err(jsonrpc_core::Error::invalid_request()),
+                id,
+                Some(Version::V2),
+            ))
+        }
+    };
+    method_call.id = Id::Num(index as u64);
+    let jsonrpc = method_call.jsonrpc;
+    Prepared::Send(id, jsonrpc, Call::MethodCall(method_call))
+}
+
+/// Converts a Web3 error into a JSON RPC error, preserving JSON RPC errors
+/// from the wallet and the node.
+fn rpc_error(err: web3::Error) -> jsonrpc_core::Error {
+    match err {
+        web3::Error::Rpc(err) => err,
+        err => jsonrpc_core::Error {
+            code: jsonrpc_core::ErrorCode::InternalError,
+            message: err.to_string(),
+            data: None,
+        },
+    }
+}
+
+/// Returns true if the request has a JSON content type.
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+}
+
+/// Reads a request body, returning `None` if it is larger than the maximum
+/// request size.
+async fn read_body(mut body: Body) -> Result<Option<Vec<u8>>, hyper::Error> {
+    if body.size_hint().lower() > MAX_REQUEST_SIZE as u64 {
+        return Ok(None);
+    }
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if bytes.len() + chunk.len() > MAX_REQUEST_SIZE {
+            return Ok(None);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Some(bytes))
+}
+
+fn status(status: StatusCode) -> hyper::Response<Body> {
+    let mut response = hyper::Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::tests::StandIn;
+    use futures::executor::block_on;
+    use jsonrpc_core::ErrorCode;
+    use serde_json::{json, Value};
+
+    /// A stand-in transport that echoes the method of requests, and fails
+    /// `eth_fail` requests with a JSON RPC error.
+    fn echo() -> StandIn {
+        StandIn::new(|method, _| match method {
+            "eth_fail" => Err(web3::Error::Rpc(jsonrpc_core::Error {
+                code: ErrorCode::ServerError(4001),
+                message: "rejected".into(),
+                data: None,
+            })),
+            method => Ok(json!(method)),
+        })
+    }
+
+    fn handle(proxy: &Proxy<StandIn>, request: Value) -> Option<Value> {
+        let request = serde_json::from_value(request).unwrap();
+        block_on(proxy.handle(request)).map(|response| json!(response))
+    }
+
+    #[test]
+    fn single_requests() {
+        let proxy = Proxy::new(echo());
+        assert_eq!(
+            handle(
+                &proxy,
+                json!({ "jsonrpc": "2.0", "method": "eth_accounts", "id": "a" })
+            ),
+            Some(json!({ "jsonrpc": "2.0", "result": "eth_accounts", "id": "a" }))
+        );
+        assert_eq!(
+            handle(
+                &proxy,
+                json!({ "jsonrpc": "2.0", "method": "eth_fail", "params": [], "id": 1 })
+            ),
+            Some(json!({
+                "jsonrpc": "2.0",
+                "error": { "code": 4001, "message": "rejected" },
+                "id": 1,
+            }))
+        );
+        assert_eq!(
+            handle(&proxy, json!({ "jsonrpc": "2.0", "method": "eth_chainId" })),
+            None
+        );
+    }
+
+    #[test]
+    fn batch_requests() {
+        let proxy = Proxy::new(echo());
+        assert_eq!(
+            handle(
+                &proxy,
+                json!([
+                    { "jsonrpc": "2.0", "method": "eth_chainId", "id": "a" },
+                    { "jsonrpc": "2.0", "method": "eth_blockNumber" },
+                    { "foo": "bar" },
+                    { "jsonrpc": "2.0", "method": "eth_fail", "id": 7 },
+                ])
+            ),
+            Some(json!([
+                { "jsonrpc": "2.0", "result": "eth_chainId", "id": "a" },
+                {
+                    "jsonrpc": "2.0",
+                    "error": { "code": -32600, "message": "Invalid request" },
+                    "id": null,
+                },
+                {
+                    "jsonrpc": "2.0",
+                    "error": { "code": 4001, "message": "rejected" },
+                    "id": 7,
+                },
+            ]))
+        );
+        assert_eq!(proxy.transport().ids(), vec![0, 1, 3]);
+    }
+
+    #[test]
+    fn empty_batch_requests() {
+        let proxy = Proxy::new(echo());
+        assert_eq!(
+            handle(&proxy, json!([])),
+            Some(json!({
+                "jsonrpc": "2.0",
+                "error": { "code": -32600, "message": "Invalid request" },
+                "id": null,
+            }))
+        );
+        assert_eq!(
+            handle(
+                &proxy,
+                json!([{ "jsonrpc": "2.0", "method": "eth_blockNumber" }])
+            ),
+            None
+        );
+    }
+
+    fn respond(request: hyper::Request<Body>) -> StatusCode {
+        block_on(Proxy::new(echo()).respond(request)).status()
+    }
+
+    #[test]
+    fn http_requests() {
+        let request = r#"{ "jsonrpc": "2.0", "method": "eth_chainId", "id": 1 }"#;
+        let json =
+            || hyper::Request::post("/").header(CONTENT_TYPE, "application/json; charset=utf-8");
+
+        assert_eq!(
+            respond(json().body(request.into()).unwrap()),
+            StatusCode::OK
+        );
+        assert_eq!(
+            respond(hyper::Request::get("/").body(Body::empty()).unwrap()),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        assert_eq!(
+            respond(
+                json()
+                    .header(ORIGIN, "https://example.com")
+                    .body(request.into())
+                    .unwrap()
+            ),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            respond(
+                hyper::Request::post("/")
+                    .header(CONTENT_TYPE, "text/plain")
+                    .body(request.into())
+                    .unwrap()
+            ),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+        assert_eq!(
+            respond(
+                json()
+                    .body(vec![b' '; MAX_REQUEST_SIZE + 1].into())
+                    .unwrap()
+            ),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+}
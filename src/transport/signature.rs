@@ -0,0 +1,268 @@
+//! Smart-contract wallet signature validation with ERC-1271 and ERC-6492.
+
+use crate::hex;
+use ethers_core::abi::{self, ParamType, Token};
+use ethers_core::types::{Address, Bytes, Signature, H256};
+use serde_json::{json, Value};
+use std::convert::TryFrom;
+use web3::Transport;
+
+/// The ERC-1271 `isValidSignature(bytes32,bytes)` selector, which is also the
+/// magic value returned for valid signatures.
+const ERC1271_MAGIC_VALUE: [u8; 4] = [0x16, 0x26, 0xba, 0x7e];
+
+/// The suffix of ERC-6492 wrapped signatures for counterfactual accounts.
+const ERC6492_MAGIC_SUFFIX: [u8; 32] = [
+    0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92,
+    0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92,
+];
+
+/// Init code for a deployless `eth_call` that deploys a counterfactual account
+/// and checks a signature with it.
+///
+/// The code expects the following data to be appended to it:
+/// `factory ++ signer ++ len(deploy) ++ len(check) ++ deploy ++ check`, where
+/// the addresses and lengths are 32-byte words. It calls the factory with the
+/// deploy calldata (ignoring any failure, in case the account was already
+/// deployed), and then returns the first word of the result of calling the
+/// signer with the check calldata, or zero if that call fails.
+const DEPLOYLESS_VALIDATOR: [u8; 63] = [
+    0x38, 0x61, 0x00, 0x3f, 0x80, 0x91, 0x03, 0x90, 0x60, 0x00,
+    0x39, // CODECOPY(0, 63, CODESIZE - 63)
+    0x60, 0x00, 0x60, 0x00, 0x60, 0x40, 0x51, 0x60, 0x80, 0x60, 0x00, 0x60, 0x00, 0x51, 0x5a, 0xf1,
+    0x50, // POP(CALL(GAS, factory, 0, 0x80, len(deploy), 0, 0))
+    0x60, 0x00, 0x60, 0x00, 0x52, // MSTORE(0, 0)
+    0x60, 0x20, 0x60, 0x00, 0x60, 0x60, 0x51, 0x60, 0x40, 0x51, 0x60, 0x80, 0x01, 0x60, 0x20, 0x51,
+    0x5a, 0xfa, // STATICCALL(GAS, signer, 0x80 + len(deploy), len(check), 0, 32)
+    0x60, 0x00, 0x51, 0x02, 0x60, 0x00, 0x52, // MSTORE(0, MLOAD(0) * success)
+    0x60, 0x20, 0x60, 0x00, 0xf3, // RETURN(0, 32)
+];
+
+/// Verifies a signature for a message hash, supporting externally owned
+/// accounts, deployed ERC-1271 smart-contract wallets and ERC-6492 signatures
+/// from counterfactual smart-contract wallets that are not yet deployed.
+///
+/// Contract signatures are checked with `eth_call`s on the specified node
+/// transport. Calls that revert are treated as invalid signatures, while
+/// transport errors are returned.
+pub async fn is_valid_signature<T>(
+    transport: &T,
+    signer: Address,
+    hash: H256,
+    signature: &[u8],
+) -> Result<bool, web3::Error>
+where
+    T: Transport,
+{
+    if let Some((factory, deploy, signature)) = unwrap_erc6492(signature) {
+        let code = transport
+            .execute("eth_getCode", vec![json!(signer), json!("latest")])
+            .await?;
+        let deployed = !serde_json::from_value::<Bytes>(code)?.as_ref().is_empty();
+
+        return if deployed {
+            is_valid_contract_signature(transport, signer, hash, &signature).await
+        } else {
+            is_valid_counterfactual_signature(transport, factory, deploy, signer, hash, &signature)
+                .await
+        };
+    }
+
+    if let Ok(ecdsa) = Signature::try_from(signature) {
+        if ecdsa.recover(hash).ok() == Some(signer) {
+            return Ok(true);
+        }
+    }
+
+    is_valid_contract_signature(transport, signer, hash, signature).await
+}
+
+/// Unwraps an ERC-6492 signature into its factory address, factory calldata
+/// and the inner signature.
+fn unwrap_erc6492(signature: &[u8]) -> Option<(Address, Vec<u8>, Vec<u8>)> {
+    let wrapped = signature.strip_suffix(&ERC6492_MAGIC_SUFFIX)?;
+    let mut tokens = abi::decode(
+        &[ParamType::Address, ParamType::Bytes, ParamType::Bytes],
+        wrapped,
+    )
+    .ok()?
+    .into_iter();
+
+    match (tokens.next()?, tokens.next()?, tokens.next()?) {
+        (Token::Address(factory), Token::Bytes(deploy), Token::Bytes(signature)) => {
+            Some((factory, deploy, signature))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the calldata for an ERC-1271 `isValidSignature` call.
+fn is_valid_signature_calldata(hash: H256, signature: &[u8]) -> Vec<u8> {
+    let mut calldata = ERC1271_MAGIC_VALUE.to_vec();
+    calldata.extend(abi::encode(&[
+        Token::FixedBytes(hash.as_bytes().to_vec()),
+        Token::Bytes(signature.to_vec()),
+    ]));
+    calldata
+}
+
+async fn is_valid_contract_signature<T>(
+    transport: &T,
+    signer: Address,
+    hash: H256,
+    signature: &[u8],
+) -> Result<bool, web3::Error>
+where
+    T: Transport,
+{
+    let call = json!({
+        "to": signer,
+        "data": prefixed_hex(&is_valid_signature_calldata(hash, signature)),
+    });
+    eth_call_returns_magic_value(transport, call).await
+}
+
+async fn is_valid_counterfactual_signature<T>(
+    transport: &T,
+    factory: Address,
+    deploy: Vec<u8>,
+    signer: Address,
+    hash: H256,
+    signature: &[u8],
+) -> Result<bool, web3::Error>
+where
+    T: Transport,
+{
+    let check = is_valid_signature_calldata(hash, signature);
+
+    let mut code = DEPLOYLESS_VALIDATOR.to_vec();
+    code.extend(abi::encode(&[
+        Token::Address(factory),
+        Token::Address(signer),
+        Token::Uint(deploy.len().into()),
+        Token::Uint(check.len().into()),
+    ]));
+    code.extend(deploy);
+    code.extend(check);
+
+    let call = json!({ "data": prefixed_hex(&code) });
+    eth_call_returns_magic_value(transport, call).await
+}
+
+async fn eth_call_returns_magic_value<T>(transport: &T, call: Value) -> Result<bool, web3::Error>
+where
+    T: Transport,
+{
+    let result = match transport
+        .execute("eth_call", vec![call, json!("latest")])
+        .await
+    {
+        Ok(result) => serde_json::from_value::<Bytes>(result)?,
+        // NOTE: Reverts are reported as RPC errors, and mean that the
+        // signature is not valid.
+        Err(web3::Error::Rpc(_)) => return Ok(false),
+        Err(err) => return Err(err),
+    };
+
+    Ok(result.as_ref().starts_with(&ERC1271_MAGIC_VALUE))
+}
+
+fn prefixed_hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::tests::StandIn;
+    use futures::executor::block_on;
+
+    /// A stand-in node that serves `eth_getCode` and `eth_call` requests.
+    fn stand_in(code: Vec<u8>, result: Option<Vec<u8>>) -> StandIn {
+        StandIn::new(move |method, _| match method {
+            "eth_getCode" => Ok(json!(prefixed_hex(&code))),
+            "eth_call" => match &result {
+                Some(result) => Ok(json!(prefixed_hex(result))),
+                None => Err(web3::Error::Rpc(jsonrpc_core::Error::new(
+                    jsonrpc_core::ErrorCode::ServerError(3),
+                ))),
+            },
+            _ => Err(web3::Error::Unreachable),
+        })
+    }
+
+    fn eth_calls(node: &StandIn) -> Vec<Value> {
+        node.params("eth_call")
+            .into_iter()
+            .map(|params| params[0].clone())
+            .collect()
+    }
+
+    fn magic_word() -> Vec<u8> {
+        let mut word = ERC1271_MAGIC_VALUE.to_vec();
+        word.resize(32, 0);
+        word
+    }
+
+    fn call_data(call: &Value) -> Vec<u8> {
+        hex::decode(&call["data"].as_str().unwrap()[2..]).unwrap()
+    }
+
+    #[test]
+    fn validates_erc1271_signatures() {
+        let signer = Address::repeat_byte(0x5a);
+        let hash = H256::repeat_byte(1);
+        let signature = [2; 65];
+
+        let node = stand_in(vec![0xfe], Some(magic_word()));
+        assert!(block_on(is_valid_signature(&node, signer, hash, &signature)).unwrap());
+
+        let calls = eth_calls(&node);
+        assert_eq!(calls[0]["to"], json!(signer));
+        assert_eq!(
+            call_data(&calls[0]),
+            is_valid_signature_calldata(hash, &signature)
+        );
+
+        for result in [Some(vec![0; 32]), None] {
+            let node = stand_in(vec![], result);
+            assert!(!block_on(is_valid_signature(&node, signer, hash, &signature)).unwrap());
+        }
+    }
+
+    #[test]
+    fn validates_erc6492_signatures() {
+        let factory = Address::repeat_byte(0xfa);
+        let signer = Address::repeat_byte(0x5a);
+        let hash = H256::repeat_byte(1);
+        let deploy = vec![0xde; 36];
+        let inner = vec![2; 65];
+
+        let mut signature = abi::encode(&[
+            Token::Address(factory),
+            Token::Bytes(deploy.clone()),
+            Token::Bytes(inner.clone()),
+        ]);
+        signature.extend(ERC6492_MAGIC_SUFFIX);
+
+        let node = stand_in(vec![], Some(magic_word()));
+        assert!(block_on(is_valid_signature(&node, signer, hash, &signature)).unwrap());
+
+        let calls = eth_calls(&node);
+        assert!(calls[0].get("to").is_none());
+        let code = call_data(&calls[0]);
+        assert!(code.starts_with(&DEPLOYLESS_VALIDATOR));
+        assert!(code.ends_with(&is_valid_signature_calldata(hash, &inner)));
+
+        // Once the account is deployed, the inner signature is checked
+        // directly with ERC-1271.
+        let node = stand_in(vec![0xfe], Some(magic_word()));
+        assert!(block_on(is_valid_signature(&node, signer, hash, &signature)).unwrap());
+        let calls = eth_calls(&node);
+        assert_eq!(calls[0]["to"], json!(signer));
+        assert_eq!(
+            call_data(&calls[0]),
+            is_valid_signature_calldata(hash, &inner)
+        );
+    }
+}
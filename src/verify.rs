@@ -0,0 +1,341 @@
+//! Verification of signatures and signed payloads returned by wallets.
+
+use crate::client::NotConnectedError;
+use crate::hex;
+use crate::protocol::Transaction;
+use ethers_core::types::transaction::eip2718::{TypedTransaction, TypedTransactionError};
+use ethers_core::types::{Address, Bytes, Signature, SignatureError, TransactionRequest, H256};
+use ethers_core::utils::hash_message;
+use ethers_core::utils::rlp::Rlp;
+use std::convert::TryFrom;
+use thiserror::Error;
+
+/// A signed transaction, as returned by `eth_signTransaction`.
+#[derive(Clone, Debug)]
+pub struct SignedTransaction {
+    pub transaction: TypedTransaction,
+    pub signature: Signature,
+    pub signer: Address,
+    pub raw: Bytes,
+}
+
+impl SignedTransaction {
+    /// Decodes a signed legacy, EIP-2930 or EIP-1559 transaction and recovers
+    /// its signer.
+    pub fn decode(raw: Bytes) -> Result<Self, SignedTransactionError> {
+        let rlp = Rlp::new(&raw);
+
+        // NOTE: Typed transactions are prefixed with their type byte, while
+        // legacy transactions are plain RLP lists.
+        let (transaction, signature) = if rlp.is_list() {
+            let (request, signature) =
+                TransactionRequest::decode_signed_rlp(&rlp).map_err(TypedTransactionError::from)?;
+            (TypedTransaction::Legacy(request), signature)
+        } else {
+            TypedTransaction::decode_signed(&rlp)?
+        };
+        let signer = *transaction
+            .from()
+            .expect("decoding signed transactions recovers the signer");
+
+        Ok(SignedTransaction {
+            transaction,
+            signature,
+            signer,
+            raw,
+        })
+    }
+
+    /// Decodes a signed transaction and verifies that it matches the
+    /// requested transaction.
+    pub fn verified(raw: Bytes, requested: &Transaction) -> Result<Self, SignedTransactionError> {
+        let signed = SignedTransaction::decode(raw)?;
+        signed.verify(requested)?;
+        Ok(signed)
+    }
+
+    /// Returns the hash of the signed transaction.
+    pub fn hash(&self) -> H256 {
+        self.transaction.hash(&self.signature)
+    }
+
+    /// Verifies that the signed transaction was signed by the requested
+    /// sender and matches all of the fields that were specified in the
+    /// request.
+    pub fn verify(&self, requested: &Transaction) -> Result<(), TransactionMismatch> {
+        if self.signer != requested.from {
+            return Err(TransactionMismatch::Signer {
+                expected: requested.from,
+                actual: self.signer,
+            });
+        }
+
+        let signed = Transaction::try_from(self.transaction.clone())
+            .expect("decoded transactions have a sender and recipient address");
+        let transaction_type = signed.transaction_type.unwrap_or_default();
+
+        let matches = [
+            ("to", signed.to == requested.to),
+            ("value", signed.value == requested.value),
+            ("data", signed.data == requested.data),
+            ("nonce", optional(&requested.nonce, &signed.nonce)),
+            (
+                "gas limit",
+                optional(&requested.gas_limit, &signed.gas_limit),
+            ),
+            (
+                "gas price",
+                optional(&requested.gas_price, &signed.gas_price),
+            ),
+            (
+                "max fee per gas",
+                optional(&requested.max_fee_per_gas, &signed.max_fee_per_gas),
+            ),
+            (
+                "max priority fee per gas",
+                optional(
+                    &requested.max_priority_fee_per_gas,
+                    &signed.max_priority_fee_per_gas,
+                ),
+            ),
+            (
+                "type",
+                requested
+                    .transaction_type
+                    .is_none_or(|kind| kind == transaction_type),
+            ),
+            (
+                "access list",
+                requested.access_list.as_ref().is_none_or(|access_list| {
+                    *access_list == signed.access_list.clone().unwrap_or_default()
+                }),
+            ),
+            ("chain ID", optional(&requested.chain_id, &signed.chain_id)),
+        ];
+        match matches.iter().find(|(_, matches)| !matches) {
+            Some((field, _)) => Err(TransactionMismatch::Field(field)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Returns true if an optional requested value was either not specified, or
+/// matches the signed value.
+fn optional<T: PartialEq>(requested: &Option<T>, signed: &Option<T>) -> bool {
+    requested.is_none() || requested == signed
+}
+
+/// Computes the EIP-191 hash of a `personal_sign` message.
+///
+/// Like wallets, messages that are `0x`-prefixed hex strings are signed as the
+/// bytes they encode, and all other messages are signed as UTF-8 text.
+pub fn personal_message_hash(message: &str) -> H256 {
+    let bytes = message
+        .strip_prefix("0x")
+        .and_then(|data| hex::decode(data).ok())
+        .unwrap_or_else(|| message.as_bytes().to_vec());
+    hash_message(bytes)
+}
+
+/// Recovers the signer of a `personal_sign` signature.
+pub fn recover_personal_signer(
+    message: &str,
+    signature: &Signature,
+) -> Result<Address, SignatureError> {
+    signature.recover(personal_message_hash(message))
+}
+
+/// Verifies that a `personal_sign` signature was signed by one of the
+/// specified accounts, returning the recovered signer.
+pub fn verify_personal_signature(
+    message: &str,
+    signature: &Signature,
+    accounts: &[Address],
+) -> Result<Address, PersonalSignatureError> {
+    let signer = recover_personal_signer(message, signature)?;
+    if !accounts.contains(&signer) {
+        return Err(PersonalSignatureError::Mismatch(SignerMismatch {
+            signer,
+            accounts: accounts.to_vec(),
+        }));
+    }
+
+    Ok(signer)
+}
+
+#[derive(Debug, Error)]
+pub enum PersonalSignatureError {
+    #[error("failed to recover signer: {0}")]
+    Recovery(#[from] SignatureError),
+    #[error(transparent)]
+    NotConnected(#[from] NotConnectedError),
+    #[error(transparent)]
+    Mismatch(#[from] SignerMismatch),
+}
+
+#[derive(Clone, Debug, Error, Eq, PartialEq)]
+#[error("message signed by {signer:?} which is not one of the session accounts {accounts:?}")]
+pub struct SignerMismatch {
+    pub signer: Address,
+    pub accounts: Vec<Address>,
+}
+
+#[derive(Debug, Error)]
+pub enum SignedTransactionError {
+    #[error("failed to decode signed transaction: {0}")]
+    Decode(#[from] TypedTransactionError),
+    #[error(transparent)]
+    Mismatch(#[from] TransactionMismatch),
+}
+
+#[derive(Clone, Debug, Error, Eq, PartialEq)]
+pub enum TransactionMismatch {
+    #[error("transaction signed by {actual:?} instead of {expected:?}")]
+    Signer { expected: Address, actual: Address },
+    #[error("signed transaction {0} does not match the request")]
+    Field(&'static str),
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use ethers_core::k256::ecdsa::SigningKey;
+    use ethers_core::types::transaction::eip1559::Eip1559TransactionRequest;
+    use ethers_core::types::U256;
+    use ethers_core::utils::secret_key_to_address;
+
+    pub fn signing_key() -> SigningKey {
+        SigningKey::from_slice(&[42; 32]).unwrap()
+    }
+
+    fn sign_hash(key: &SigningKey, hash: H256) -> (U256, U256, u64) {
+        let (signature, recovery_id) = key.sign_prehash_recoverable(hash.as_bytes()).unwrap();
+        let bytes = signature.to_bytes();
+        (
+            U256::from_big_endian(&bytes[..32]),
+            U256::from_big_endian(&bytes[32..]),
+            recovery_id.to_byte().into(),
+        )
+    }
+
+    fn sign_transaction(key: &SigningKey, transaction: &TypedTransaction) -> Bytes {
+        let (r, s, recovery_id) = sign_hash(key, transaction.sighash());
+        let v = match transaction {
+            TypedTransaction::Legacy(request) => {
+                recovery_id + 35 + 2 * request.chain_id.unwrap_or_default().as_u64()
+            }
+            _ => recovery_id,
+        };
+        transaction.rlp_signed(&Signature { r, s, v })
+    }
+
+    /// Signs a message like `personal_sign` with a private key.
+    pub fn sign_message(key: &SigningKey, message: &str) -> Signature {
+        let (r, s, recovery_id) = sign_hash(key, personal_message_hash(message));
+        Signature {
+            r,
+            s,
+            v: recovery_id + 27,
+        }
+    }
+
+    fn requested() -> Transaction {
+        Transaction {
+            from: secret_key_to_address(&signing_key()),
+            to: Some(Address::repeat_byte(2)),
+            value: 1_000.into(),
+            nonce: Some(7.into()),
+            chain_id: Some(1.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn verifies_signed_transactions() {
+        let key = signing_key();
+        let requested = requested();
+
+        let legacy: TypedTransaction = Transaction {
+            gas_limit: Some(21_000.into()),
+            gas_price: Some(1_000_000_000.into()),
+            ..requested.clone()
+        }
+        .into();
+        let eip1559 = TypedTransaction::Eip1559(
+            Eip1559TransactionRequest::new()
+                .to(Address::repeat_byte(2))
+                .value(1_000)
+                .nonce(7)
+                .gas(21_000)
+                .max_fee_per_gas(100)
+                .max_priority_fee_per_gas(2)
+                .chain_id(1),
+        );
+
+        for transaction in [legacy, eip1559] {
+            let raw = sign_transaction(&key, &transaction);
+            let signed = SignedTransaction::verified(raw, &requested).unwrap();
+            assert_eq!(signed.signer, requested.from);
+            assert_eq!(signed.transaction.sighash(), transaction.sighash());
+        }
+    }
+
+    #[test]
+    fn detects_transaction_mismatches() {
+        let key = signing_key();
+        let transaction: TypedTransaction = Transaction {
+            value: 1_000_000.into(),
+            ..requested()
+        }
+        .into();
+        let signed = SignedTransaction::decode(sign_transaction(&key, &transaction)).unwrap();
+
+        assert_eq!(
+            signed.verify(&requested()),
+            Err(TransactionMismatch::Field("value"))
+        );
+        assert_eq!(
+            signed.verify(&Transaction {
+                from: Address::repeat_byte(1),
+                ..requested()
+            }),
+            Err(TransactionMismatch::Signer {
+                expected: Address::repeat_byte(1),
+                actual: signed.signer,
+            })
+        );
+    }
+
+    #[test]
+    fn personal_message_hashing() {
+        assert_eq!(
+            personal_message_hash("0x48656c6c6f"),
+            personal_message_hash("Hello")
+        );
+        assert_eq!(personal_message_hash("Hello"), hash_message("Hello"));
+        assert_eq!(
+            personal_message_hash("0xnot hex"),
+            hash_message("0xnot hex")
+        );
+    }
+
+    #[test]
+    fn verifies_personal_signatures() {
+        let key = signing_key();
+        let account = secret_key_to_address(&key);
+        let signature = sign_message(&key, "Hello WalletConnect");
+
+        assert_eq!(
+            verify_personal_signature("Hello WalletConnect", &signature, &[account]).unwrap(),
+            account
+        );
+        assert!(matches!(
+            verify_personal_signature("Hello WalletConnect", &signature, &[Address::zero()]),
+            Err(PersonalSignatureError::Mismatch(SignerMismatch { signer, .. })) if signer == account
+        ));
+        assert!(matches!(
+            verify_personal_signature("Goodbye WalletConnect", &signature, &[account]),
+            Err(PersonalSignatureError::Mismatch(_))
+        ));
+    }
+}